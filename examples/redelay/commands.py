@@ -10,7 +10,7 @@ def schedule_add(
     args: typing.List[str],
 ) -> None:
     res = r.execute_command(
-        "schedule.add", key_, int(delay.total_seconds()), *args
+        "schedule.add", key_, "PX", int(delay.total_seconds() * 1000), *args
     )
     return res
 
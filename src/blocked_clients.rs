@@ -0,0 +1,347 @@
+use redis_module::{raw, Context, RedisValue};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::os::raw::{c_int, c_void};
+use std::time::Duration;
+
+use crate::commands::{pop_due_tasks, run_reply};
+use crate::context_ext::ContextExt;
+use crate::data_types::{Outcome, ScheduleDataType, SCHEDULE_DATA_TYPE};
+
+// (Database, schedule key), as in the timers module
+type ScheduleKey = (i32, String);
+// (Database, schedule key, task id)
+type TaskKey = (i32, String, String);
+
+thread_local! {
+    // Number of clients blocked on SCHEDULE.BPOPDUE, per schedule key.
+    // Redis calls the module from its main thread only
+    static BLOCKED_CLIENTS: RefCell<HashMap<ScheduleKey, usize>> = RefCell::new(HashMap::new());
+    // Number of clients blocked on SCHEDULE.WAIT, per schedule key and task id
+    static WAITING_CLIENTS: RefCell<HashMap<ScheduleKey, HashMap<String, usize>>> = RefCell::new(HashMap::new());
+    // The last run (executed timestamp, outcome, output) of the tasks clients wait for
+    static TASK_RUNS: RefCell<HashMap<TaskKey, (u64, Outcome, String)>> = RefCell::new(HashMap::new());
+}
+
+struct BlockedPop {
+    db: i32,
+    schedule_key: String,
+    count: usize,
+}
+
+struct BlockedWait {
+    db: i32,
+    schedule_key: String,
+    task_id: String,
+    // Only the runs from this timestamp (in milliseconds) on release the client
+    since: u64,
+}
+
+///
+/// Whether clients are blocked on the schedule, in the context's selected database
+///
+pub fn has_blocked_clients(ctx: &Context, schedule_key: &str) -> bool {
+    let id = (ctx.get_selected_db(), schedule_key.to_string());
+    BLOCKED_CLIENTS.with(|clients| clients.borrow().contains_key(&id))
+}
+
+///
+/// Blocks the current client until `count` due payload tasks can be
+/// popped from the schedule, or until the timeout (zero means forever)
+///
+pub fn block_client(ctx: &Context, schedule_key: String, count: usize, timeout: Duration) {
+    let db = ctx.get_selected_db();
+    BLOCKED_CLIENTS.with(|clients| {
+        *clients
+            .borrow_mut()
+            .entry((db, schedule_key.clone()))
+            .or_insert(0) += 1;
+    });
+
+    let keys = [schedule_key.as_str()];
+    let privdata = Box::into_raw(Box::new(BlockedPop {
+        db,
+        schedule_key: schedule_key.clone(),
+        count,
+    }));
+    ctx.block_client_on_keys(
+        &keys,
+        Some(reply_callback),
+        Some(timeout_callback),
+        Some(free_privdata),
+        timeout,
+        privdata as *mut c_void,
+    );
+}
+
+// Called whenever the schedule key is signaled as ready.
+// Returning an error keeps the client blocked
+extern "C" fn reply_callback(
+    ctx: *mut raw::RedisModuleCtx,
+    _argv: *mut *mut raw::RedisModuleString,
+    _argc: c_int,
+) -> c_int {
+    let ctx = Context::new(ctx);
+    let blocked_pop = ctx.get_blocked_client_private_data() as *const BlockedPop;
+    let blocked_pop = match unsafe { blocked_pop.as_ref() } {
+        Some(blocked_pop) => blocked_pop,
+        None => return raw::REDISMODULE_ERR as c_int,
+    };
+
+    match pop_due_tasks(&ctx, &blocked_pop.schedule_key, blocked_pop.count) {
+        // The schedule was deleted (or flushed) while the client waited
+        Ok(entries) if entries.is_empty() && !holds_schedule(&ctx, &blocked_pop.schedule_key) => {
+            ctx.reply(Ok(RedisValue::Null)) as c_int
+        }
+        Ok(entries) if entries.is_empty() => raw::REDISMODULE_ERR as c_int,
+        Ok(entries) => ctx.reply(Ok(RedisValue::Array(entries))) as c_int,
+        Err(error) => ctx.reply(Err(error)) as c_int,
+    }
+}
+
+fn holds_schedule(ctx: &Context, schedule_key: &str) -> bool {
+    let key = ctx.open_key(schedule_key);
+    matches!(
+        key.get_value::<ScheduleDataType>(&SCHEDULE_DATA_TYPE),
+        Ok(Some(_))
+    )
+}
+
+extern "C" fn timeout_callback(
+    ctx: *mut raw::RedisModuleCtx,
+    _argv: *mut *mut raw::RedisModuleString,
+    _argc: c_int,
+) -> c_int {
+    let ctx = Context::new(ctx);
+    ctx.reply(Ok(RedisValue::Null)) as c_int
+}
+
+// Called once the client is unblocked: served, timed out or disconnected
+extern "C" fn free_privdata(_ctx: *mut raw::RedisModuleCtx, privdata: *mut c_void) {
+    if privdata.is_null() {
+        return;
+    }
+    let blocked_pop = unsafe { Box::from_raw(privdata as *mut BlockedPop) };
+    let id = (blocked_pop.db, blocked_pop.schedule_key);
+    BLOCKED_CLIENTS.with(|clients| {
+        let mut clients = clients.borrow_mut();
+        if let Some(blocked) = clients.get_mut(&id) {
+            *blocked -= 1;
+            if *blocked == 0 {
+                clients.remove(&id);
+            }
+        }
+    });
+}
+
+///
+/// Blocks the current client until the task runs, or until the timeout (zero means forever).
+/// The client is also released (with nil) if the task is removed
+///
+pub fn block_client_until_run(
+    ctx: &Context,
+    schedule_key: String,
+    task_id: String,
+    since: u64,
+    timeout: Duration,
+) {
+    let db = ctx.get_selected_db();
+    WAITING_CLIENTS.with(|clients| {
+        *clients
+            .borrow_mut()
+            .entry((db, schedule_key.clone()))
+            .or_default()
+            .entry(task_id.clone())
+            .or_insert(0) += 1;
+    });
+
+    let keys = [schedule_key.as_str()];
+    let privdata = Box::into_raw(Box::new(BlockedWait {
+        db,
+        schedule_key: schedule_key.clone(),
+        task_id,
+        since,
+    }));
+    ctx.block_client_on_keys(
+        &keys,
+        Some(wait_reply_callback),
+        Some(timeout_callback),
+        Some(free_wait_privdata),
+        timeout,
+        privdata as *mut c_void,
+    );
+}
+
+fn task_key(ctx: &Context, schedule_key: &str, task_id: &str) -> TaskKey {
+    (
+        ctx.get_selected_db(),
+        schedule_key.to_string(),
+        task_id.to_string(),
+    )
+}
+
+fn has_waiting_clients((db, schedule_key, task_id): &TaskKey) -> bool {
+    WAITING_CLIENTS.with(|clients| {
+        matches!(clients.borrow().get(&(*db, schedule_key.clone())), Some(tasks) if tasks.contains_key(task_id))
+    })
+}
+
+///
+/// Releases the clients waiting for the task with the outcome of its run
+///
+pub fn task_ran(
+    ctx: &Context,
+    schedule_key: &str,
+    task_id: &str,
+    executed: u64,
+    outcome: Outcome,
+    output: &str,
+) {
+    let id = task_key(ctx, schedule_key, task_id);
+    if !has_waiting_clients(&id) {
+        return;
+    }
+    TASK_RUNS.with(|runs| {
+        runs.borrow_mut()
+            .insert(id, (executed, outcome, output.to_string()));
+    });
+    ctx.signal_key_as_ready(schedule_key);
+}
+
+///
+/// Releases the clients waiting for a task that was removed without running
+///
+pub fn task_removed(ctx: &Context, schedule_key: &str, task_id: &str) {
+    if has_waiting_clients(&task_key(ctx, schedule_key, task_id)) {
+        ctx.signal_key_as_ready(schedule_key);
+    }
+}
+
+extern "C" fn wait_reply_callback(
+    ctx: *mut raw::RedisModuleCtx,
+    _argv: *mut *mut raw::RedisModuleString,
+    _argc: c_int,
+) -> c_int {
+    let ctx = Context::new(ctx);
+    let blocked_wait = ctx.get_blocked_client_private_data() as *const BlockedWait;
+    let blocked_wait = match unsafe { blocked_wait.as_ref() } {
+        Some(blocked_wait) => blocked_wait,
+        None => return raw::REDISMODULE_ERR as c_int,
+    };
+
+    let id = (
+        blocked_wait.db,
+        blocked_wait.schedule_key.clone(),
+        blocked_wait.task_id.clone(),
+    );
+    let run = TASK_RUNS.with(|runs| match runs.borrow().get(&id) {
+        Some((executed, outcome, output)) if *executed >= blocked_wait.since => {
+            Some(run_reply(*outcome, output))
+        }
+        _ => None,
+    });
+    if let Some(reply) = run {
+        return ctx.reply(Ok(reply)) as c_int;
+    }
+
+    let key = ctx.open_key(&blocked_wait.schedule_key);
+    match key.get_value::<ScheduleDataType>(&SCHEDULE_DATA_TYPE) {
+        Ok(Some(value)) if value.get_task(&blocked_wait.task_id).is_some() => {
+            raw::REDISMODULE_ERR as c_int
+        }
+        _ => ctx.reply(Ok(RedisValue::Null)) as c_int,
+    }
+}
+
+extern "C" fn free_wait_privdata(_ctx: *mut raw::RedisModuleCtx, privdata: *mut c_void) {
+    if privdata.is_null() {
+        return;
+    }
+    let BlockedWait {
+        db,
+        schedule_key,
+        task_id,
+        ..
+    } = *unsafe { Box::from_raw(privdata as *mut BlockedWait) };
+    let schedule_id = (db, schedule_key);
+    WAITING_CLIENTS.with(|clients| {
+        let mut clients = clients.borrow_mut();
+        let tasks = match clients.get_mut(&schedule_id) {
+            Some(tasks) => tasks,
+            None => return,
+        };
+        if let Some(waiting) = tasks.get_mut(&task_id) {
+            *waiting -= 1;
+            if *waiting == 0 {
+                tasks.remove(&task_id);
+                let id = (db, schedule_id.1.clone(), task_id);
+                TASK_RUNS.with(|runs| runs.borrow_mut().remove(&id));
+            }
+        }
+        if tasks.is_empty() {
+            clients.remove(&schedule_id);
+        }
+    });
+}
+
+// The schedules clients are blocked on (SCHEDULE.BPOPDUE or SCHEDULE.WAIT) in the databases
+fn blocked_schedule_keys(dbs: impl Fn(i32) -> bool) -> Vec<ScheduleKey> {
+    let mut schedule_keys: Vec<ScheduleKey> = BLOCKED_CLIENTS.with(|clients| {
+        clients
+            .borrow()
+            .keys()
+            .filter(|(db, _)| dbs(*db))
+            .cloned()
+            .collect()
+    });
+    WAITING_CLIENTS.with(|clients| {
+        schedule_keys.extend(clients.borrow().keys().filter(|(db, _)| dbs(*db)).cloned());
+    });
+    schedule_keys.sort();
+    schedule_keys.dedup();
+    schedule_keys
+}
+
+///
+/// SWAPDB swaps the keys of two databases, but the blocked clients stay on their database:
+/// they now wait on the schedules swapped in, whose due tasks may be for them.
+/// The runs recorded for the waiting clients belonged to the schedules swapped out
+///
+pub fn dbs_swapped(ctx: &Context, first: i32, second: i32) {
+    let swapped = |db: i32| db == first || db == second;
+    let schedule_keys = blocked_schedule_keys(swapped);
+    TASK_RUNS.with(|runs| runs.borrow_mut().retain(|(db, _, _), _| !swapped(*db)));
+
+    for (db, schedule_key) in schedule_keys {
+        if ctx.select_db(db) {
+            crate::open_key_and_update_timer(ctx, schedule_key.clone(), None);
+            ctx.signal_key_as_ready(&schedule_key);
+        }
+    }
+}
+
+///
+/// Releases (with nil) the clients blocked on the schedules of a flushed database
+/// (all of them if None)
+///
+pub fn db_flushed(ctx: &Context, flushed_db: Option<i32>) {
+    let schedule_keys = blocked_schedule_keys(|db| flushed_db.is_none() || flushed_db == Some(db));
+    for (db, schedule_key) in schedule_keys {
+        if ctx.select_db(db) {
+            ctx.signal_key_as_ready(&schedule_key);
+        }
+    }
+}
+
+///
+/// Releases (with nil) the clients blocked on a schedule that was deleted, expired,
+/// evicted, renamed or moved, unless one of its tasks ran for them
+///
+pub fn schedule_removed(ctx: &Context, schedule_key: &str) {
+    let id = (ctx.get_selected_db(), schedule_key.to_string());
+    let blocked = BLOCKED_CLIENTS.with(|clients| clients.borrow().contains_key(&id))
+        || WAITING_CLIENTS.with(|clients| clients.borrow().contains_key(&id));
+    if blocked {
+        ctx.signal_key_as_ready(schedule_key);
+    }
+}
@@ -1,208 +1,2133 @@
 use redis_module::{Context, NextArg, RedisError, RedisResult, RedisValue};
+use std::iter::Peekable;
 use std::string::String;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use std::vec::Vec;
 use uuid::Uuid;
 
+use crate::blocked_clients;
 use crate::context_ext::ContextExt;
+use crate::cron::CronSchedule;
+use crate::hash_slot::key_hash_slot;
+use crate::timers;
 
 use super::{
-    exec_task, open_key_and_update_timer, update_timer, ScheduleDataType, SCHEDULE_DATA_TYPE,
+    exec_task, open_key_and_update_timer, update_timer, DeadLetter, Deliver, Drain, Guard,
+    HistoryEntry, Lease, Misfire, Outcome, Recurrence, RetryPolicy, ScheduleDataType, Task,
+    SCHEDULE_DATA_TYPE,
 };
 
+// Replies and errors are truncated to this many characters in the history
+const HISTORY_OUTPUT_LEN: usize = 256;
+
+// The last millisecond of the year 9999, tasks can't be scheduled any later
+const MAX_TIMESTAMP: u64 = 253_402_300_799_999;
+
+///
+/// Fails unless the timestamp (None if it overflowed) is at most MAX_TIMESTAMP
+///
+fn valid_timestamp(timestamp: Option<u64>) -> Result<u64, RedisError> {
+    timestamp
+        .filter(|timestamp| *timestamp <= MAX_TIMESTAMP)
+        .ok_or(RedisError::Str("ERR invalid expire time"))
+}
+
+///
+/// Reads a time argument, either a number of seconds or `<unit_keyword> milliseconds`
+///
+/// Returns the value in milliseconds and how many arguments were consumed
+///
+fn next_time_arg<I>(args: &mut I, unit_keyword: &str) -> Result<(u64, i32), RedisError>
+where
+    I: Iterator<Item = String>,
+{
+    let value = args.next_string()?;
+    if value.eq_ignore_ascii_case(unit_keyword) {
+        return Ok((args.next_u64()?, 2));
+    }
+    value
+        .parse::<u64>()
+        .ok()
+        .and_then(|seconds| seconds.checked_mul(1000))
+        .map(|milliseconds| (milliseconds, 1))
+        .ok_or(RedisError::Str(
+            "ERR value is not an integer or out of range",
+        ))
+}
+
+///
+/// Reads a duration: `seconds` or `PX milliseconds`
+///
+fn next_duration<I>(args: &mut I) -> Result<(u64, i32), RedisError>
+where
+    I: Iterator<Item = String>,
+{
+    next_time_arg(args, "PX")
+}
+
+///
+/// Reads a unix timestamp: `seconds` or `PXAT milliseconds`
+///
+fn next_timestamp<I>(args: &mut I) -> Result<(u64, i32), RedisError>
+where
+    I: Iterator<Item = String>,
+{
+    let (timestamp, timestamp_len) = next_time_arg(args, "PXAT")?;
+    Ok((valid_timestamp(Some(timestamp))?, timestamp_len))
+}
+
+///
+/// Reads a misfire policy: `RUN_ALL | RUN_LATEST_ONLY | SKIP_IF_LATE_BY (seconds | PX ms)`
+/// `| DEADLETTER_IF_LATE (seconds | PX ms)`
+///
+/// Returns it and how many arguments were consumed
+///
+fn next_misfire<I>(args: &mut I) -> Result<(Misfire, i32), RedisError>
+where
+    I: Iterator<Item = String>,
+{
+    match args.next_string()?.to_uppercase().as_str() {
+        "RUN_ALL" => Ok((Misfire::RunAll, 1)),
+        "RUN_LATEST_ONLY" => Ok((Misfire::RunLatestOnly, 1)),
+        "SKIP_IF_LATE_BY" => {
+            let (late_by, late_by_len) = next_duration(args)?;
+            Ok((Misfire::SkipIfLateBy(late_by), 1 + late_by_len))
+        }
+        "DEADLETTER_IF_LATE" => {
+            let (late_by, late_by_len) = next_duration(args)?;
+            Ok((Misfire::DeadLetterIfLateBy(late_by), 1 + late_by_len))
+        }
+        _ => Err(RedisError::Str("ERR syntax error")),
+    }
+}
+
+///
+/// Inverse of next_misfire
+///
+fn misfire_args(misfire: &Misfire) -> Vec<String> {
+    match misfire {
+        Misfire::RunAll => vec!["RUN_ALL".to_string()],
+        Misfire::RunLatestOnly => vec!["RUN_LATEST_ONLY".to_string()],
+        Misfire::SkipIfLateBy(late_by) => vec![
+            "SKIP_IF_LATE_BY".to_string(),
+            "PX".to_string(),
+            late_by.to_string(),
+        ],
+        Misfire::DeadLetterIfLateBy(late_by) => vec![
+            "DEADLETTER_IF_LATE".to_string(),
+            "PX".to_string(),
+            late_by.to_string(),
+        ],
+    }
+}
+
+///
+/// Parses the optional task settings that come before the delayed command:
+/// `[EVERY (seconds | PX ms)] [TIMES n] [UNTIL (timestamp | PXAT ms)]`
+/// `[RETRY n BACKOFF (seconds | PX ms) [MAX (seconds | PX ms)]] [PAYLOAD payload]`
+/// `[IF (EXISTS key | NOT EXISTS key | EQ key value)]`
+/// `[ONSUCCESS numargs command [arg ...]] [ONFAILURE numargs command [arg ...]]`
+/// `[AFTER task-id[,task-id...]] [MISFIRE policy]`
+///
+/// `internal` enables the options that only SCHEDULE.REPLICATE uses to restore
+/// the state of a task: `[CRON expression] [ATTEMPTS n] [LASTERROR error] [DELIVERIES n]`
+/// `[LEASE consumer (timestamp | PXAT ms)] [MULTI] [PARKED]`
+///
+/// Returns how many arguments were consumed, and the positions of the
+/// keys (relative to the first option) the options refer to
+///
+fn parse_task_options<I>(
+    ctx: &Context,
+    args: &mut Peekable<I>,
+    task: &mut Task,
+    internal: bool,
+) -> Result<(i32, Vec<i32>), RedisError>
+where
+    I: Iterator<Item = String>,
+{
+    let mut consumed = 0;
+    let mut keys = Vec::new();
+    let mut retries = None;
+    let mut backoff = None;
+    let mut max_backoff = None;
+    while let Some(option) = args.peek().map(|x| x.to_uppercase()) {
+        match option.as_str() {
+            // SCHEDULE.CRON computes the first run from the expression, the other
+            // commands would run a cron task at their own time first
+            "CRON" | "EVERY" if internal || option == "EVERY" => {
+                args.next();
+                if task.recurrence.is_some() {
+                    return Err(RedisError::Str("ERR only one of CRON or EVERY can be used"));
+                }
+                task.recurrence = Some(if option == "CRON" {
+                    let expression = args.next_string()?;
+                    CronSchedule::parse(&expression)
+                        .map_err(|e| RedisError::String(e.to_string()))?;
+                    consumed += 2;
+                    Recurrence::Cron(expression)
+                } else {
+                    let (interval, interval_len) = next_duration(args)?;
+                    consumed += 1 + interval_len;
+                    match interval {
+                        0 => return Err(RedisError::Str("ERR EVERY must be positive")),
+                        interval => Recurrence::Every(interval),
+                    }
+                });
+            }
+            "TIMES" => {
+                args.next();
+                task.remaining_runs = match args.next_u64()? {
+                    0 => return Err(RedisError::Str("ERR TIMES must be positive")),
+                    times => Some(times),
+                };
+                consumed += 2;
+            }
+            "UNTIL" => {
+                args.next();
+                let (until, until_len) = next_timestamp(args)?;
+                task.until = Some(until);
+                consumed += 1 + until_len;
+            }
+            "RETRY" => {
+                args.next();
+                retries = match args.next_u64()? {
+                    0 => return Err(RedisError::Str("ERR RETRY must be positive")),
+                    retries => Some(retries),
+                };
+                consumed += 2;
+            }
+            "BACKOFF" | "MAX" => {
+                args.next();
+                let (delay, delay_len) = next_duration(args)?;
+                if option == "BACKOFF" {
+                    backoff = Some(delay);
+                } else {
+                    max_backoff = Some(delay);
+                }
+                consumed += 1 + delay_len;
+            }
+            "PAYLOAD" => {
+                args.next();
+                task.payload = Some(args.next_string()?);
+                consumed += 2;
+            }
+            "IF" => {
+                args.next();
+                if task.guard.is_some() {
+                    return Err(RedisError::Str("ERR only one IF can be used"));
+                }
+                let (guard, key_pos, guard_len) = match args.next_string()?.to_uppercase().as_str()
+                {
+                    "EXISTS" => (Guard::Exists(args.next_string()?), 2, 3),
+                    "EQ" => {
+                        let key = args.next_string()?;
+                        (Guard::Eq(key, args.next_string()?), 2, 4)
+                    }
+                    "NOT" => {
+                        if !args.next_string()?.eq_ignore_ascii_case("EXISTS") {
+                            return Err(RedisError::Str("ERR syntax error"));
+                        }
+                        (Guard::NotExists(args.next_string()?), 3, 4)
+                    }
+                    _ => return Err(RedisError::Str("ERR syntax error")),
+                };
+                task.guard = Some(guard);
+                keys.push(consumed + key_pos);
+                consumed += guard_len;
+            }
+            "AFTER" => {
+                args.next();
+                task.after = args.next_string()?.split(',').map(String::from).collect();
+                if task.after.iter().any(|x| x.is_empty()) {
+                    return Err(RedisError::Str("ERR syntax error"));
+                }
+                consumed += 2;
+            }
+            "MISFIRE" => {
+                args.next();
+                let (misfire, misfire_len) = next_misfire(args)?;
+                task.misfire = Some(misfire);
+                consumed += 1 + misfire_len;
+            }
+            "ONSUCCESS" | "ONFAILURE" => {
+                args.next();
+                let numargs = args.next_u64()? as usize;
+                let callback: Vec<String> = args.by_ref().take(numargs).collect();
+                if callback.len() != numargs {
+                    return Err(RedisError::WrongArity);
+                }
+                keys.extend(
+                    ctx.get_command_keys(&callback)?
+                        .iter()
+                        .map(|key_pos| consumed + 2 + key_pos),
+                );
+                consumed += 2 + numargs as i32;
+                if option == "ONSUCCESS" {
+                    task.on_success = Some(callback);
+                } else {
+                    task.on_failure = Some(callback);
+                }
+            }
+            "ATTEMPTS" if internal => {
+                args.next();
+                task.attempts = args.next_u64()?;
+                consumed += 2;
+            }
+            "LASTERROR" if internal => {
+                args.next();
+                task.last_error = Some(args.next_string()?);
+                consumed += 2;
+            }
+            "DELIVERIES" if internal => {
+                args.next();
+                task.deliveries = args.next_u64()?;
+                consumed += 2;
+            }
+            "LEASE" if internal => {
+                args.next();
+                let consumer = args.next_string()?;
+                let (until, until_len) = next_timestamp(args)?;
+                task.lease = Some(Lease { consumer, until });
+                consumed += 2 + until_len;
+            }
+            "MULTI" if internal => {
+                args.next();
+                task.transaction = true;
+                consumed += 1;
+            }
+            "PARKED" if internal => {
+                args.next();
+                task.parked = true;
+                consumed += 1;
+            }
+            _ => break,
+        }
+    }
+
+    if task.recurrence.is_none() && (task.remaining_runs.is_some() || task.until.is_some()) {
+        return Err(RedisError::Str(
+            "ERR TIMES and UNTIL can only be used with CRON or EVERY",
+        ));
+    }
+    task.retry = match (retries, backoff, max_backoff) {
+        (None, None, None) => None,
+        (Some(retries), Some(backoff), max_backoff) => Some(RetryPolicy {
+            retries,
+            backoff,
+            max_backoff,
+        }),
+        _ => {
+            return Err(RedisError::Str(
+                "ERR RETRY and BACKOFF must be used together, MAX requires both",
+            ))
+        }
+    };
+    if task.payload.is_some() && task.retry.is_some() {
+        return Err(RedisError::Str("ERR RETRY can't be used with PAYLOAD"));
+    }
+    // A recurring task already moved to its next run when a run fails
+    if !internal && task.recurrence.is_some() && task.retry.is_some() {
+        return Err(RedisError::Str(
+            "ERR RETRY can't be used with CRON or EVERY",
+        ));
+    }
+    if task.payload.is_some() && task.guard.is_some() {
+        return Err(RedisError::Str("ERR IF can't be used with PAYLOAD"));
+    }
+    if task.payload.is_some() && (task.on_success.is_some() || task.on_failure.is_some()) {
+        return Err(RedisError::Str(
+            "ERR ONSUCCESS and ONFAILURE can't be used with PAYLOAD",
+        ));
+    }
+    Ok((consumed, keys))
+}
+
+///
+/// SCHEDULE.ADD's `[ID task-id] [NX | XX] [GT | LT]` options, as in ZADD
+///
+#[derive(Default)]
+struct AddOptions {
+    task_id: Option<String>,
+    nx: bool,
+    xx: bool,
+    gt: bool,
+    lt: bool,
+}
+
+impl AddOptions {
+    // Whether the task can be added, given the timestamp
+    // of the task with the same id (if any)
+    fn allows(&self, current_timestamp: Option<u64>, timestamp: u64) -> bool {
+        match current_timestamp {
+            None => !self.xx,
+            Some(current_timestamp) => {
+                !self.nx
+                    && (!self.gt || timestamp > current_timestamp)
+                    && (!self.lt || timestamp < current_timestamp)
+            }
+        }
+    }
+}
+
+///
+/// Parses SCHEDULE.ADD's options that apply to the task id, not to the task
+///
+/// Returns them and how many arguments were consumed
+///
+fn parse_add_options<I>(args: &mut Peekable<I>) -> Result<(AddOptions, i32), RedisError>
+where
+    I: Iterator<Item = String>,
+{
+    let mut options = AddOptions::default();
+    let mut consumed = 0;
+    while let Some(option) = args.peek().map(|x| x.to_uppercase()) {
+        match option.as_str() {
+            "ID" => {
+                args.next();
+                options.task_id = Some(args.next_string()?);
+                consumed += 2;
+            }
+            "NX" | "XX" | "GT" | "LT" => {
+                args.next();
+                match option.as_str() {
+                    "NX" => options.nx = true,
+                    "XX" => options.xx = true,
+                    "GT" => options.gt = true,
+                    _ => options.lt = true,
+                }
+                consumed += 1;
+            }
+            _ => break,
+        }
+    }
+
+    if options.nx && options.xx {
+        return Err(RedisError::Str("ERR NX and XX can't be used together"));
+    }
+    if (options.gt && options.lt) || (options.nx && (options.gt || options.lt)) {
+        return Err(RedisError::Str(
+            "ERR GT, LT, and NX options at the same time are not compatible",
+        ));
+    }
+    if options.task_id.is_none() && (options.nx || options.xx || options.gt || options.lt) {
+        return Err(RedisError::Str("ERR NX, XX, GT and LT require ID"));
+    }
+    Ok((options, consumed))
+}
+
+///
+/// Inverse of parse_task_options, used to replicate a task
+///
+fn task_options(task: &Task) -> Vec<String> {
+    let mut options = Vec::new();
+    match &task.recurrence {
+        Some(Recurrence::Cron(expression)) => {
+            options.push("CRON".to_string());
+            options.push(expression.clone());
+        }
+        Some(Recurrence::Every(interval)) => {
+            options.push("EVERY".to_string());
+            options.push("PX".to_string());
+            options.push(interval.to_string());
+        }
+        None => {}
+    }
+    if let Some(remaining_runs) = task.remaining_runs {
+        options.push("TIMES".to_string());
+        options.push(remaining_runs.to_string());
+    }
+    if let Some(until) = task.until {
+        options.push("UNTIL".to_string());
+        options.push("PXAT".to_string());
+        options.push(until.to_string());
+    }
+    if let Some(retry) = &task.retry {
+        options.push("RETRY".to_string());
+        options.push(retry.retries.to_string());
+        options.push("BACKOFF".to_string());
+        options.push("PX".to_string());
+        options.push(retry.backoff.to_string());
+        if let Some(max_backoff) = retry.max_backoff {
+            options.push("MAX".to_string());
+            options.push("PX".to_string());
+            options.push(max_backoff.to_string());
+        }
+    }
+    if task.attempts > 0 {
+        options.push("ATTEMPTS".to_string());
+        options.push(task.attempts.to_string());
+    }
+    if let Some(last_error) = &task.last_error {
+        options.push("LASTERROR".to_string());
+        options.push(last_error.clone());
+    }
+    if task.deliveries > 0 {
+        options.push("DELIVERIES".to_string());
+        options.push(task.deliveries.to_string());
+    }
+    if let Some(lease) = &task.lease {
+        options.push("LEASE".to_string());
+        options.push(lease.consumer.clone());
+        options.push("PXAT".to_string());
+        options.push(lease.until.to_string());
+    }
+    if task.transaction {
+        options.push("MULTI".to_string());
+    }
+    if task.parked {
+        options.push("PARKED".to_string());
+    }
+    match &task.guard {
+        Some(Guard::Exists(key)) => {
+            options.push("IF".to_string());
+            options.push("EXISTS".to_string());
+            options.push(key.clone());
+        }
+        Some(Guard::NotExists(key)) => {
+            options.push("IF".to_string());
+            options.push("NOT".to_string());
+            options.push("EXISTS".to_string());
+            options.push(key.clone());
+        }
+        Some(Guard::Eq(key, value)) => {
+            options.push("IF".to_string());
+            options.push("EQ".to_string());
+            options.push(key.clone());
+            options.push(value.clone());
+        }
+        None => {}
+    }
+    for (option, callback) in [
+        ("ONSUCCESS", &task.on_success),
+        ("ONFAILURE", &task.on_failure),
+    ] {
+        if let Some(callback) = callback {
+            options.push(option.to_string());
+            options.push(callback.len().to_string());
+            options.extend(callback.iter().cloned());
+        }
+    }
+    if !task.after.is_empty() {
+        options.push("AFTER".to_string());
+        options.push(task.after.join(","));
+    }
+    if let Some(misfire) = &task.misfire {
+        options.push("MISFIRE".to_string());
+        options.extend(misfire_args(misfire));
+    }
+    if let Some(payload) = &task.payload {
+        options.push("PAYLOAD".to_string());
+        options.push(payload.clone());
+    }
+    options
+}
+
+///
+/// Positions of the keys in the task's command (or the commands of its transaction)
+///
+/// Fails unless the task has either a command or a payload
+///
+fn task_command_keys(ctx: &Context, task: &Task) -> Result<Vec<i32>, RedisError> {
+    match (&task.payload, task.args.is_empty()) {
+        (None, _) if task.transaction => {
+            let mut keys = Vec::new();
+            let mut offset = 0;
+            for command in task.commands() {
+                keys.extend(
+                    ctx.get_command_keys(command)?
+                        .iter()
+                        .map(|key_pos| offset + key_pos),
+                );
+                offset += command.len() as i32 + 1; // The command and its `;`
+            }
+            Ok(keys)
+        }
+        (None, _) => ctx.get_command_keys(&task.args),
+        (Some(_), true) => Ok(Vec::new()),
+        (Some(_), false) => Err(RedisError::Str(
+            "ERR a task can't have both a PAYLOAD and a command",
+        )),
+    }
+}
+
+///
+/// Replicate a SCHEDULE.REPLICATE command (the whole task) to the AOF and replicas
+///
+fn replicate_task(ctx: &Context, schedule_key: &str, task_id: &str, task: &Task) {
+    let timestamp_str = task.timestamp.to_string();
+    let options = task_options(task);
+    // key + PXAT ts + id + [options] + [command]
+    let mut replicate_args: Vec<&str> = Vec::with_capacity(4 + options.len() + task.args.len());
+    replicate_args.push(schedule_key);
+    replicate_args.push("PXAT");
+    replicate_args.push(&timestamp_str);
+    replicate_args.push(task_id);
+    replicate_args.extend(options.iter().map(|x| x.as_str()));
+    replicate_args.extend(task.args.iter().map(|x| x.as_str()));
+    ctx.replicate("SCHEDULE.REPLICATE", &replicate_args);
+}
+
+///
+/// Helper function to add a task to a schedule.
+/// If the schedule doesn't exist, it will create it.
+///
+/// Replicate a SCHEDULE.REPLICATE command to the AOF
+///
+fn add_task_helper_to_schedule(
+    ctx: &Context,
+    schedule_key: String,
+    task: Task,
+    task_id: Option<String>,
+) -> Result<String, RedisError> {
+    let key = ctx.open_key_writable(&schedule_key);
+    let value = key.get_value::<ScheduleDataType>(&SCHEDULE_DATA_TYPE)?;
+
+    let task_id = task_id.unwrap_or_else(|| Uuid::new_v4().to_hyphenated().to_string());
+
+    let has_parents = task.after.iter().all(|parent_id| {
+        parent_id != &task_id
+            && matches!(&value, Some(value) if value.get_task(parent_id).is_some())
+    });
+    if !has_parents {
+        return Err(RedisError::Str(
+            "ERR AFTER refers to a task that isn't in the schedule",
+        ));
+    }
+    // The task replaces the one with its id, which its parents may wait for
+    let makes_cycle = task
+        .after
+        .iter()
+        .any(|parent_id| matches!(&value, Some(value) if value.waits_for(parent_id, &task_id)));
+    if makes_cycle {
+        return Err(RedisError::Str(
+            "ERR AFTER would make the tasks wait for each other",
+        ));
+    }
+
+    replicate_task(ctx, &schedule_key, &task_id, &task);
+    match value {
+        Some(value) => {
+            value.insert_task(task_id.clone(), task);
+        }
+        None => {
+            let mut value = ScheduleDataType::new();
+            value.insert_task(task_id.clone(), task);
+            key.set_value(&SCHEDULE_DATA_TYPE, value)?;
+        }
+    };
+
+    open_key_and_update_timer(&ctx, schedule_key, None);
+
+    Ok(task_id)
+}
+
+///
+/// SCHEDULE.REPLICATE key (timestamp | PXAT ms) task_id [options] CMD...
+///
+pub fn replicate(ctx: &Context, args: Vec<String>) -> RedisResult {
+    ctx.log_notice(format!("{:?}", args).as_str());
+
+    let mut args = args.into_iter().skip(1).peekable();
+    let schedule_key = args.next_string()?;
+    let (timestamp, timestamp_len) = next_timestamp(&mut args)?;
+    let task_id = args.next_string()?;
+    let mut task = Task::new(timestamp, Vec::new());
+    let (options_len, option_keys) = parse_task_options(ctx, &mut args, &mut task, true)?;
+    task.args = args.collect();
+
+    let command_keys = task_command_keys(ctx, &task)?;
+    if ctx.is_keys_position_request() {
+        let offset = 3 + timestamp_len + options_len; // (0)SCHEDULE.REPLICATE (1)KEY (2)TIMESTAMP (3)task_id [options] [CMD] ==
+        ctx.key_at_pos(1);
+        for key_pos in option_keys {
+            ctx.key_at_pos(offset - options_len + key_pos);
+        }
+        for key_pos in command_keys {
+            ctx.key_at_pos(offset + key_pos);
+        }
+        return Ok(RedisValue::NoReply);
+    }
+
+    let task_id = add_task_helper_to_schedule(ctx, schedule_key, task, Some(task_id))?;
+    Ok(RedisValue::BulkString(task_id))
+}
+
+///
+/// SCHEDULE.ADD key (delay | PX ms) [ID task-id] [NX | XX] [GT | LT] [options] CMD...
+///
+/// With an id, the task replaces the one with the same id (if any), unless NX/XX/GT/LT
+/// prevent it. Then it returns nil
+///
+pub fn add(ctx: &Context, args: Vec<String>) -> RedisResult {
+    ctx.log_notice(format!("{:?}", args).as_str());
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+
+    let mut args = args.into_iter().skip(1).peekable();
+    let schedule_key = args.next_string()?;
+    let (delay, delay_len) = next_duration(&mut args)?;
+    let timestamp = valid_timestamp((now.as_millis() as u64).checked_add(delay))?;
+    let (add_options, add_options_len) = parse_add_options(&mut args)?;
+    let mut task = Task::new(timestamp, Vec::new());
+    let (options_len, option_keys) = parse_task_options(ctx, &mut args, &mut task, false)?;
+    task.args = args.collect();
+
+    let command_keys = task_command_keys(ctx, &task)?;
+    if ctx.is_keys_position_request() {
+        let offset = 2 + delay_len + add_options_len + options_len; // (0)SCHEDULE.ADD (1)KEY (2)DELAY [ID/NX/XX/GT/LT] [options] [CMD] ==
+        ctx.key_at_pos(1);
+        for key_pos in option_keys {
+            ctx.key_at_pos(offset - options_len + key_pos);
+        }
+        for key_pos in command_keys {
+            ctx.key_at_pos(offset + key_pos);
+        }
+        return Ok(RedisValue::NoReply);
+    }
+
+    if let Some(task_id) = &add_options.task_id {
+        let key = ctx.open_key(&schedule_key);
+        let current_timestamp = key
+            .get_value::<ScheduleDataType>(&SCHEDULE_DATA_TYPE)?
+            .and_then(|value| value.get_task(task_id))
+            .map(|current| current.timestamp);
+        if !add_options.allows(current_timestamp, timestamp) {
+            return Ok(RedisValue::Null);
+        }
+    }
+
+    let task_id = add_task_helper_to_schedule(ctx, schedule_key, task, add_options.task_id)?;
+    Ok(RedisValue::BulkString(task_id))
+}
+
+///
+/// SCHEDULE.DEBOUNCE key task-id (delay | PX ms) CMD...
+///
+/// Pushes the task back to now + delay and replaces its command,
+/// or adds it with this id if it isn't in the schedule
+///
+pub fn debounce(ctx: &Context, args: Vec<String>) -> RedisResult {
+    ctx.log_notice(format!("{:?}", args).as_str());
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+
+    let mut args = args.into_iter().skip(1).peekable();
+    let schedule_key = args.next_string()?;
+    let task_id = args.next_string()?;
+    let (delay, delay_len) = next_duration(&mut args)?;
+    let timestamp = valid_timestamp((now.as_millis() as u64).checked_add(delay))?;
+    let task = Task::new(timestamp, args.collect());
+
+    let command_keys = task_command_keys(ctx, &task)?;
+    if ctx.is_keys_position_request() {
+        let offset = 3 + delay_len; // (0)SCHEDULE.DEBOUNCE (1)KEY (2)TASK_ID (3)DELAY [CMD] ==
+        ctx.key_at_pos(1);
+        for key_pos in command_keys {
+            ctx.key_at_pos(offset + key_pos);
+        }
+        return Ok(RedisValue::NoReply);
+    }
+
+    let key = ctx.open_key_writable(&schedule_key);
+    let rearmed = match key.get_value::<ScheduleDataType>(&SCHEDULE_DATA_TYPE)? {
+        Some(value) => match value.rearm_task(task_id.clone(), timestamp, task.args.clone()) {
+            Some(task) => {
+                replicate_task(ctx, &schedule_key, &task_id, task);
+                true
+            }
+            None => false,
+        },
+        None => false,
+    };
+    if rearmed {
+        open_key_and_update_timer(ctx, schedule_key, Some(now));
+    } else {
+        add_task_helper_to_schedule(ctx, schedule_key, task, Some(task_id.clone()))?;
+    }
+    Ok(RedisValue::BulkString(task_id))
+}
+
+///
+/// SCHEDULE.THROTTLE key task-id (window | PX ms) CMD...
+///
+/// Adds the task with this id at the end of the window, unless it's already
+/// in the schedule. Returns the task's timestamp, as SCHEDULE.SETAT does
+///
+pub fn throttle(ctx: &Context, args: Vec<String>) -> RedisResult {
+    ctx.log_notice(format!("{:?}", args).as_str());
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+
+    let mut args = args.into_iter().skip(1).peekable();
+    let schedule_key = args.next_string()?;
+    let task_id = args.next_string()?;
+    let (window, window_len) = next_duration(&mut args)?;
+    let timestamp = valid_timestamp((now.as_millis() as u64).checked_add(window))?;
+    let task = Task::new(timestamp, args.collect());
+
+    let command_keys = task_command_keys(ctx, &task)?;
+    if ctx.is_keys_position_request() {
+        let offset = 3 + window_len; // (0)SCHEDULE.THROTTLE (1)KEY (2)TASK_ID (3)WINDOW [CMD] ==
+        ctx.key_at_pos(1);
+        for key_pos in command_keys {
+            ctx.key_at_pos(offset + key_pos);
+        }
+        return Ok(RedisValue::NoReply);
+    }
+
+    {
+        let key = ctx.open_key(&schedule_key);
+        if let Some(value) = key.get_value::<ScheduleDataType>(&SCHEDULE_DATA_TYPE)? {
+            if let Some(pending) = value.get_task(&task_id) {
+                return Ok(RedisValue::BulkString(pending.timestamp.to_string()));
+            }
+        }
+    }
+
+    add_task_helper_to_schedule(ctx, schedule_key, task, Some(task_id))?;
+    Ok(RedisValue::BulkString(timestamp.to_string()))
+}
+
+///
+/// SCHEDULE.ADDMULTI key (delay | PX ms) [options] N CMD... [; CMD...]
+///
+/// Same as SCHEDULE.ADD, but the task runs N `;`-separated commands
+/// one after the other, with nothing running in between, all-or-nothing
+///
+pub fn add_multi(ctx: &Context, args: Vec<String>) -> RedisResult {
+    ctx.log_notice(format!("{:?}", args).as_str());
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+
+    let mut args = args.into_iter().skip(1).peekable();
+    let schedule_key = args.next_string()?;
+    let (delay, delay_len) = next_duration(&mut args)?;
+    let timestamp = valid_timestamp((now.as_millis() as u64).checked_add(delay))?;
+    let mut task = Task {
+        transaction: true,
+        ..Task::new(timestamp, Vec::new())
+    };
+    let (options_len, option_keys) = parse_task_options(ctx, &mut args, &mut task, false)?;
+    if task.payload.is_some() {
+        return Err(RedisError::Str("ERR PAYLOAD can't be used with ADDMULTI"));
+    }
+    let count = args.next_u64()?;
+    task.args = args.collect();
+    if task.commands().len() as u64 != count {
+        return Err(RedisError::Str(
+            "ERR the number of commands doesn't match N",
+        ));
+    }
+
+    let command_keys = task_command_keys(ctx, &task)?;
+    if ctx.is_keys_position_request() {
+        let options_offset = 2 + delay_len; // (0)SCHEDULE.ADDMULTI (1)KEY (2)DELAY [options] (N) [CMD] ==
+        let offset = options_offset + options_len + 1;
+        ctx.key_at_pos(1);
+        for key_pos in option_keys {
+            ctx.key_at_pos(options_offset + key_pos);
+        }
+        for key_pos in command_keys {
+            ctx.key_at_pos(offset + key_pos);
+        }
+        return Ok(RedisValue::NoReply);
+    }
+
+    let task_id = add_task_helper_to_schedule(ctx, schedule_key, task, None)?;
+    Ok(RedisValue::BulkString(task_id))
+}
+
+///
+/// SCHEDULE.ADDAT key (timestamp | PXAT ms) [options] CMD...
+///
+pub fn addat(ctx: &Context, args: Vec<String>) -> RedisResult {
+    ctx.log_notice(format!("{:?}", args).as_str());
+
+    let mut args = args.into_iter().skip(1).peekable();
+    let schedule_key = args.next_string()?;
+    let (timestamp, timestamp_len) = next_timestamp(&mut args)?;
+    let mut task = Task::new(timestamp, Vec::new());
+    let (options_len, option_keys) = parse_task_options(ctx, &mut args, &mut task, false)?;
+    task.args = args.collect();
+
+    let command_keys = task_command_keys(ctx, &task)?;
+    if ctx.is_keys_position_request() {
+        let offset = 2 + timestamp_len + options_len; // (0)SCHEDULE.ADDAT (1)KEY (2)TIMESTAMP [options] [CMD] ==
+        ctx.key_at_pos(1);
+        for key_pos in option_keys {
+            ctx.key_at_pos(offset - options_len + key_pos);
+        }
+        for key_pos in command_keys {
+            ctx.key_at_pos(offset + key_pos);
+        }
+        return Ok(RedisValue::NoReply);
+    }
+
+    let task_id = add_task_helper_to_schedule(ctx, schedule_key, task, None)?;
+    Ok(RedisValue::BulkString(task_id))
+}
+
+///
+/// SCHEDULE.CRON key cron-expression [TIMES n] [UNTIL timestamp] CMD...
+///
+pub fn cron(ctx: &Context, args: Vec<String>) -> RedisResult {
+    ctx.log_notice(format!("{:?}", args).as_str());
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+
+    let mut args = args.into_iter().skip(1).peekable();
+    let schedule_key = args.next_string()?;
+    let expression = args.next_string()?;
+    let timestamp = CronSchedule::parse(&expression)
+        .map_err(|e| RedisError::String(e.to_string()))?
+        .next_after(now.as_secs())
+        .ok_or(RedisError::Str("ERR cron expression never matches"))?
+        * 1000;
+    let mut task = Task {
+        recurrence: Some(Recurrence::Cron(expression)),
+        ..Task::new(timestamp, Vec::new())
+    };
+    let (options_len, option_keys) = parse_task_options(ctx, &mut args, &mut task, false)?;
+    task.args = args.collect();
+
+    let command_keys = task_command_keys(ctx, &task)?;
+    if ctx.is_keys_position_request() {
+        let offset = 3 + options_len; // (0)SCHEDULE.CRON (1)KEY (2)EXPRESSION [options] [CMD] ==
+        ctx.key_at_pos(1);
+        for key_pos in option_keys {
+            ctx.key_at_pos(offset - options_len + key_pos);
+        }
+        for key_pos in command_keys {
+            ctx.key_at_pos(offset + key_pos);
+        }
+        return Ok(RedisValue::NoReply);
+    }
+
+    let task_id = add_task_helper_to_schedule(ctx, schedule_key, task, None)?;
+    Ok(RedisValue::BulkString(task_id))
+}
+
+///
+/// SCHEDULE.REM key task-id
+///
+pub fn rem(ctx: &Context, args: Vec<String>) -> RedisResult {
+    ctx.log_notice(format!("{:?}", args).as_str());
+
+    let mut args = args.into_iter().skip(1);
+    let schedule_key = args.next_string()?;
+    let task_id = args.next_string()?;
+    let key = ctx.open_key_writable(&schedule_key);
+
+    let removed = match key.get_value::<ScheduleDataType>(&SCHEDULE_DATA_TYPE)? {
+        Some(value) => {
+            let removed = value.del_task(task_id.clone()).is_some();
+            ctx.replicate_verbatim();
+            removed
+        }
+        None => false,
+    };
+    if removed {
+        blocked_clients::task_removed(ctx, &schedule_key, &task_id);
+        open_key_and_update_timer(ctx, schedule_key.clone(), None);
+    }
+    // The master replicates what happens to the tasks waiting for it
+    if removed && !ctx.is_replicated_or_loading() {
+        cancel_dependents(ctx, &schedule_key, &task_id, "ERR the task was removed")?;
+    }
+    Ok(RedisValue::Null)
+}
+
+///
+/// SCHEDULE.ADVANCE key task-id (timestamp | PXAT ms) [TIMES n]
+///
+/// Moves a recurring task to its next run
+///
+pub fn advance(ctx: &Context, args: Vec<String>) -> RedisResult {
+    ctx.log_notice(format!("{:?}", args).as_str());
+
+    let mut args = args.into_iter().skip(1);
+    let schedule_key = args.next_string()?;
+    let task_id = args.next_string()?;
+    let (timestamp, _) = next_timestamp(&mut args)?;
+    let remaining_runs = match args.next() {
+        Some(option) if option.eq_ignore_ascii_case("TIMES") => Some(args.next_u64()?),
+        Some(_) => return Err(RedisError::Str("ERR syntax error")),
+        None => None,
+    };
+    let key = ctx.open_key_writable(&schedule_key);
+
+    match key.get_value::<ScheduleDataType>(&SCHEDULE_DATA_TYPE)? {
+        Some(value) => {
+            value.advance_task(task_id, timestamp, remaining_runs);
+            ctx.replicate_verbatim();
+            open_key_and_update_timer(ctx, schedule_key, None);
+            Ok(RedisValue::Null)
+        }
+        None => Ok(RedisValue::Null),
+    }
+}
+
+///
+/// Moves a task to its next run (SCHEDULE.ADVANCE) or removes it from
+/// the schedule (SCHEDULE.REM) once it ran, and replicates the change
+///
+/// Returns the task's next run, if any
+///
+fn finish_task_run(
+    ctx: &Context,
+    schedule_key: &str,
+    task_id: &str,
+    task: &Task,
+    schedule: &mut ScheduleDataType,
+    now: u64,
+) -> Option<Task> {
+    let next_task = task.next_run(now);
+    match &next_task {
+        Some(next_task) => {
+            let timestamp_str = next_task.timestamp.to_string();
+            let mut advance_args = vec![schedule_key, task_id, "PXAT", &timestamp_str];
+            let remaining_runs_str = next_task.remaining_runs.map(|x| x.to_string());
+            if let Some(remaining_runs_str) = &remaining_runs_str {
+                advance_args.push("TIMES");
+                advance_args.push(remaining_runs_str);
+            }
+            ctx.replicate("SCHEDULE.ADVANCE", &advance_args);
+            schedule.advance_task(
+                task_id.to_string(),
+                next_task.timestamp,
+                next_task.remaining_runs,
+            );
+        }
+        None => {
+            ctx.replicate("SCHEDULE.REM", &[schedule_key, task_id]);
+            schedule.del_task(task_id.to_string());
+        }
+    }
+    next_task
+}
+
+///
+/// The tasks waiting for a task that ran successfully stop waiting for it,
+/// they are replicated (SCHEDULE.REPLICATE) without the dependency
+///
+fn release_dependents(
+    ctx: &Context,
+    schedule_key: &str,
+    schedule: &mut ScheduleDataType,
+    parent_id: &str,
+) {
+    for task_id in schedule.complete_dependency(parent_id) {
+        if let Some(task) = schedule.get_task(&task_id) {
+            replicate_task(ctx, schedule_key, &task_id, task);
+        }
+    }
+}
+
+///
+/// Removes the tasks waiting for a task that won't run successfully (and the
+/// ones waiting for them), replicating SCHEDULE.REM for each of them.
+/// They go to the schedule's dead-letter target, if any
+///
+fn cancel_dependents(
+    ctx: &Context,
+    schedule_key: &str,
+    parent_id: &str,
+    error: &str,
+) -> Result<(), RedisError> {
+    let (cancelled, dead_letter) = {
+        let key = ctx.open_key_writable(schedule_key);
+        match key.get_value::<ScheduleDataType>(&SCHEDULE_DATA_TYPE)? {
+            Some(value) => (
+                value.cancel_dependents(parent_id),
+                value.config.dead_letter.clone(),
+            ),
+            None => return Ok(()),
+        }
+    };
+
+    let error = format!("ERR task {} didn't run: {}", parent_id, error);
+    for (task_id, task) in cancelled {
+        ctx.replicate("SCHEDULE.REM", &[schedule_key, &task_id]);
+        blocked_clients::task_removed(ctx, schedule_key, &task_id);
+        if let Some(dead_letter) = &dead_letter {
+            dead_letter_task(ctx, schedule_key, &task_id, &task, &error, dead_letter)?;
+        }
+    }
+    Ok(())
+}
+
+///
+/// Puts the tasks whose lease expired at `now` back into the timetable,
+/// replicating a SCHEDULE.NACK for each of them
+///
+fn release_expired_leases(
+    ctx: &Context,
+    schedule_key: &str,
+    schedule: &mut ScheduleDataType,
+    now: u64,
+) {
+    for task_id in schedule.expired_lease_ids(now) {
+        ctx.replicate("SCHEDULE.NACK", &[schedule_key, &task_id]);
+        schedule.release_task(task_id);
+    }
+}
+
+///
+/// Moves a run that failed for the last time to the schedule's dead-letter target
+///
+/// It replicates its own command (SCHEDULE.REPLICATE or XADD with the entry's id)
+///
+fn dead_letter_task(
+    ctx: &Context,
+    schedule_key: &str,
+    task_id: &str,
+    task: &Task,
+    error: &str,
+    dead_letter: &DeadLetter,
+) -> Result<(), RedisError> {
+    match dead_letter {
+        DeadLetter::Schedule(target) => {
+            // Only this run goes to the dead-letter schedule, where it doesn't execute
+            // until it's done by hand. Payload tasks wait for the target's workers instead
+            let dead_task = Task {
+                recurrence: None,
+                remaining_runs: None,
+                until: None,
+                retry: None,
+                after: Vec::new(),
+                attempts: 0,
+                last_error: Some(error.to_string()),
+                parked: task.payload.is_none(),
+                ..task.clone()
+            };
+            add_task_helper_to_schedule(ctx, target.clone(), dead_task, Some(task_id.to_string()))?;
+        }
+        DeadLetter::Stream(target) => {
+            let timestamp_str = task.timestamp.to_string();
+            let command_str = serde_json::to_string(&task.args)?;
+            let mut xadd_args = vec![
+                target.as_str(),
+                "*",
+                "schedule",
+                schedule_key,
+                "id",
+                task_id,
+                "timestamp",
+                &timestamp_str,
+                "command",
+                &command_str,
+                "error",
+                error,
+            ];
+            // Replicate the entry's id instead of letting replicas generate theirs
+            match ctx.call("XADD", &xadd_args)? {
+                RedisValue::SimpleString(entry_id) | RedisValue::BulkString(entry_id) => {
+                    xadd_args[1] = &entry_id;
+                    ctx.replicate("XADD", &xadd_args);
+                }
+                _ => ctx.replicate("XADD", &xadd_args),
+            }
+        }
+    }
+    Ok(())
+}
+
+///
+/// Whether a task's guard holds, right before running its command
+///
+fn guard_holds(ctx: &Context, guard: &Guard) -> Result<bool, RedisError> {
+    match guard {
+        Guard::Exists(key) | Guard::NotExists(key) => {
+            let exists = matches!(ctx.call("EXISTS", &[key])?, RedisValue::Integer(n) if n > 0);
+            Ok(exists == matches!(guard, Guard::Exists(_)))
+        }
+        Guard::Eq(key, value) => Ok(match ctx.call("GET", &[key])? {
+            RedisValue::BulkString(current) | RedisValue::SimpleString(current) => {
+                &current == value
+            }
+            _ => false,
+        }),
+    }
+}
+
+///
+/// Text of a command's reply, used to fill the `{reply}` placeholder
+///
+fn reply_to_string(reply: &RedisValue) -> String {
+    match reply {
+        RedisValue::SimpleStringStatic(s) => s.to_string(),
+        RedisValue::SimpleString(s) | RedisValue::BulkString(s) => s.clone(),
+        RedisValue::Integer(n) => n.to_string(),
+        RedisValue::Float(n) => n.to_string(),
+        RedisValue::Array(values) => {
+            let values: Vec<String> = values.iter().map(reply_to_string).collect();
+            serde_json::to_string(&values).unwrap_or_default()
+        }
+        _ => String::new(),
+    }
+}
+
+///
+/// Adds a run (or a failed attempt) to the schedule's history, if it keeps one,
+/// and replicates a SCHEDULE.RECORD command
+///
+/// It doesn't release the clients waiting for the task (see `blocked_clients::task_ran`)
+///
+fn record_run(
+    ctx: &Context,
+    schedule_key: &str,
+    task: &Task,
+    task_id: &str,
+    now: u64,
+    outcome: Outcome,
+    output: &str,
+) {
+    let key = ctx.open_key_writable(schedule_key);
+    let value = match key.get_value::<ScheduleDataType>(&SCHEDULE_DATA_TYPE) {
+        Ok(Some(value)) if value.config.history > 0 => value,
+        _ => return,
+    };
+    let output = match output.char_indices().nth(HISTORY_OUTPUT_LEN) {
+        Some((end, _)) => &output[..end],
+        None => output,
+    };
+    let scheduled = task.timestamp.to_string();
+    let executed = now.to_string();
+    ctx.replicate(
+        "SCHEDULE.RECORD",
+        &[
+            schedule_key,
+            task_id,
+            "PXAT",
+            &scheduled,
+            "PXAT",
+            &executed,
+            outcome.as_str(),
+            output,
+        ],
+    );
+    value.record_run(HistoryEntry {
+        task_id: task_id.to_string(),
+        scheduled: task.timestamp,
+        executed: now,
+        outcome,
+        output: output.to_string(),
+    });
+}
+
+///
+/// Runs a task's ONSUCCESS/ONFAILURE command, replacing the `placeholder` arguments
+/// with `value`. The command is replicated if it succeeds, it only logs a failure
+///
+fn run_callback(
+    ctx: &Context,
+    schedule_key: &str,
+    task_id: &str,
+    callback: &[String],
+    placeholder: &str,
+    value: &str,
+) {
+    let command: Vec<String> = callback
+        .iter()
+        .map(|arg| {
+            if arg == placeholder {
+                value.to_string()
+            } else {
+                arg.clone()
+            }
+        })
+        .collect();
+    match exec_task(ctx, &command) {
+        Ok(_) => {
+            let args: Vec<&str> = command[1..].iter().map(|x| x.as_str()).collect();
+            ctx.replicate(&command[0], &args);
+        }
+        Err(error) => {
+            let msg = format!(
+                "Failed to execute the callback of task (key={}, id={}, callback={:?}); Error={:#?}",
+                schedule_key, task_id, command, error
+            );
+            ctx.log_warning(&msg);
+        }
+    }
+}
+
+///
+/// The command (XADD) that delivers a due payload task
+///
+fn delivery_command(deliver: &Deliver, task_id: &str, task: &Task, now: u64) -> Vec<String> {
+    let Deliver::Stream { key, maxlen } = deliver;
+    xadd_command(
+        key,
+        maxlen.map(|maxlen| ("~", maxlen)),
+        "*",
+        task_id,
+        task,
+        now,
+    )
+}
+
+///
+/// The delivery command as it's replicated: with the entry's id, and trimming the stream
+/// to its exact length after the XADD, replicas and the AOF could trim it to another length
+///
+fn replicated_delivery_command(
+    ctx: &Context,
+    deliver: &Deliver,
+    entry_id: &str,
+    task_id: &str,
+    task: &Task,
+    now: u64,
+) -> Vec<String> {
+    let Deliver::Stream { key, maxlen } = deliver;
+    let trim = maxlen.map(|maxlen| match ctx.call("XLEN", &[key]) {
+        Ok(RedisValue::Integer(len)) => ("=", len as u64),
+        _ => ("~", maxlen),
+    });
+    xadd_command(key, trim, entry_id, task_id, task, now)
+}
+
+// XADD key [MAXLEN (~ | =) n] id id task-id scheduled ts delivered ts payload payload
+fn xadd_command(
+    key: &str,
+    trim: Option<(&str, u64)>,
+    entry_id: &str,
+    task_id: &str,
+    task: &Task,
+    now: u64,
+) -> Vec<String> {
+    let mut command = vec!["XADD".to_string(), key.to_string()];
+    if let Some((strategy, maxlen)) = trim {
+        command.push("MAXLEN".to_string());
+        command.push(strategy.to_string());
+        command.push(maxlen.to_string());
+    }
+    command.push(entry_id.to_string());
+    command.push("id".to_string());
+    command.push(task_id.to_string());
+    command.push("scheduled".to_string());
+    command.push(task.timestamp.to_string());
+    command.push("delivered".to_string());
+    command.push(now.to_string());
+    command.push("payload".to_string());
+    command.push(task.payload.clone().unwrap_or_default());
+    command
+}
+
+///
+/// Helper function to execute task from a schedule,
+///
+/// This function will propagate two items into the AOF:
+///     1. SCHEDULE.REM (remove this task from the schedule) or
+///        SCHEDULE.ADVANCE (move a recurring task to its next run)
+///     2. [COMMAND] (the command this task executed, if it succeeded)
+///
+/// If the command fails, the task is scheduled again (SCHEDULE.REPLICATE) when it
+/// has retries left. Otherwise, this run goes to the schedule's dead-letter target.
+/// Recurring tasks are scheduled again even if this run failed
+///
+/// A payload task runs the schedule's delivery command instead (see `delivery_command`).
+/// The commands of a transaction are all-or-nothing: they run until one fails, and then
+/// the keys they wrote are restored as they were (see `keys_written_first`) and none of
+/// them is replicated. If the keys can't be restored, the run fails without retries.
+/// Otherwise, they're replicated with the rest of the task's changes (as a MULTI/EXEC block)
+///
+/// If the task's guard doesn't hold, the run is skipped (nothing executes) and it returns SKIPPED
+///
+/// The ONSUCCESS command runs after a successful run, the ONFAILURE command after the
+/// last failed attempt. They are replicated after the commands they follow.
+/// The clients waiting for the task (SCHEDULE.WAIT) are released at the same points
+///
+/// Important: This function will not create/update timers, this
+/// is something that must be handled by the caller
+///
+fn execute_schedule_task(ctx: &Context, schedule_key: String, task_id: String) -> RedisResult {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+
+    // Update the schedule before running the command, it may change the schedule itself
+    let (task, next_task, dead_letter, deliver, mut commands) = {
+        let key = ctx.open_key_writable(&schedule_key);
+        let value = match key.get_value::<ScheduleDataType>(&SCHEDULE_DATA_TYPE)? {
+            Some(value) => value,
+            None => return Ok(RedisValue::Null),
+        };
+        let task = match value.get_task(&task_id) {
+            Some(task) => task.clone(),
+            None => return Ok(RedisValue::Null),
+        };
+        if !task.after.is_empty() {
+            return Err(RedisError::Str("ERR the task waits for other tasks to run"));
+        }
+
+        let commands = match (&task.payload, &value.config.deliver) {
+            (None, _) => task.commands().iter().map(|x| x.to_vec()).collect(),
+            (Some(_), Some(deliver)) => vec![delivery_command(deliver, &task_id, &task, now)],
+            (Some(_), None) => {
+                return Err(RedisError::Str(
+                    "ERR payload tasks are consumed with SCHEDULE.POPDUE",
+                ))
+            }
+        };
+
+        let next_task = finish_task_run(ctx, &schedule_key, &task_id, &task, value, now);
+        (
+            task,
+            next_task,
+            value.config.dead_letter.clone(),
+            value.config.deliver.clone(),
+            commands,
+        )
+    };
+
+    // A guard that can't be evaluated fails the run
+    let guard = match &task.guard {
+        Some(guard) => guard_holds(ctx, guard),
+        None => Ok(true),
+    };
+    if let Ok(false) = guard {
+        let msg = format!(
+            "Skipped task (key={}, id={}), its guard doesn't hold: {:?}",
+            schedule_key, task_id, task.guard
+        );
+        ctx.log_notice(&msg);
+        record_run(
+            ctx,
+            &schedule_key,
+            &task,
+            &task_id,
+            now,
+            Outcome::Skipped,
+            "",
+        );
+        blocked_clients::task_ran(ctx, &schedule_key, &task_id, now, Outcome::Skipped, "");
+        if next_task.is_none() {
+            cancel_dependents(ctx, &schedule_key, &task_id, "its IF condition didn't hold")?;
+        }
+        return Ok(RedisValue::SimpleStringStatic("SKIPPED"));
+    }
+
+    // Don't start a transaction that can't run to the end
+    let mut written_keys = None;
+    let mut result = guard.and_then(|_| {
+        if task.transaction {
+            written_keys = Some(keys_written_first(ctx, &commands)?);
+        }
+        Ok(RedisValue::Null)
+    });
+    let mut snapshot = Snapshot::new();
+    let mut executed = 0;
+    if result.is_ok() {
+        for (i, command) in commands.iter().enumerate() {
+            if let Some(written_keys) = &written_keys {
+                result =
+                    snapshot_keys(ctx, &written_keys[i], &mut snapshot).map(|_| RedisValue::Null);
+                if result.is_err() {
+                    break;
+                }
+            }
+            result = exec_task(ctx, command);
+            if result.is_err() {
+                break;
+            }
+            executed += 1;
+        }
+    }
+    // Keys left half rolled back fail the run for good, it isn't retried
+    let mut rolled_back = true;
+    if let (Some(_), Err(error)) = (&written_keys, &result) {
+        executed = 0;
+        if let Err(restore_error) = restore_keys(ctx, &snapshot) {
+            let msg = format!(
+                "Failed to roll back task (key={}, id={}); Error={:#?}",
+                schedule_key, task_id, restore_error
+            );
+            ctx.log_warning(&msg);
+            result = Err(RedisError::String(format!(
+                "{} (rolling back the task's keys failed: {})",
+                error, restore_error
+            )));
+            rolled_back = false;
+        }
+    }
+    // Replicate the stream entry's id instead of letting replicas generate theirs
+    if let (Some(_), Some(deliver), Ok(RedisValue::SimpleString(entry_id)))
+    | (Some(_), Some(deliver), Ok(RedisValue::BulkString(entry_id))) =
+        (&task.payload, &deliver, &result)
+    {
+        commands[0] = replicated_delivery_command(ctx, deliver, entry_id, &task_id, &task, now);
+    }
+    for command in &commands[..executed] {
+        let mut args_iter = command.iter();
+        let cmd = args_iter.next().unwrap().as_str();
+        let args: Vec<&str> = args_iter.map(|x| x.as_str()).collect();
+        ctx.replicate(cmd, &args);
+    }
+
+    if let Err(error) = result {
+        let msg = format!(
+            "Failed to execute task (key={}, id={}, task={:?}); Error={:#?}",
+            schedule_key, task_id, task, error
+        );
+        ctx.log_warning(&msg);
+
+        let error_msg = error.to_string();
+        record_run(
+            ctx,
+            &schedule_key,
+            &task,
+            &task_id,
+            now,
+            Outcome::Failure,
+            &error_msg,
+        );
+        // The clients waiting for the task wait for its last attempt
+        let retry_task = task.retry(now, error_msg.clone()).filter(|_| rolled_back);
+        if let Some(retry_task) = retry_task {
+            add_task_helper_to_schedule(ctx, schedule_key, retry_task, Some(task_id))?;
+            return Err(error);
+        }
+        blocked_clients::task_ran(
+            ctx,
+            &schedule_key,
+            &task_id,
+            now,
+            Outcome::Failure,
+            &error_msg,
+        );
+
+        if let Some(on_failure) = &task.on_failure {
+            run_callback(
+                ctx,
+                &schedule_key,
+                &task_id,
+                on_failure,
+                "{error}",
+                &error_msg,
+            );
+        }
+        if next_task.is_none() {
+            cancel_dependents(ctx, &schedule_key, &task_id, &error_msg).unwrap_or_else(
+                |cancel_error| {
+                    let msg = format!(
+                        "Failed to cancel the tasks waiting for task (key={}, id={}); Error={:#?}",
+                        schedule_key, task_id, cancel_error
+                    );
+                    ctx.log_warning(&msg);
+                },
+            );
+        }
+
+        if let Some(dead_letter) = &dead_letter {
+            dead_letter_task(ctx, &schedule_key, &task_id, &task, &error_msg, dead_letter)
+                .unwrap_or_else(|dead_letter_error| {
+                    let msg = format!(
+                        "Failed to dead-letter task (key={}, id={}, target={:?}); Error={:#?}",
+                        schedule_key, task_id, dead_letter, dead_letter_error
+                    );
+                    ctx.log_warning(&msg);
+                });
+        }
+        if let Some(next_task) = next_task {
+            let next_task = Task {
+                last_error: Some(error_msg),
+                ..next_task
+            };
+            add_task_helper_to_schedule(ctx, schedule_key, next_task, Some(task_id))?;
+        }
+        return Err(error);
+    }
+
+    let reply = result.as_ref().map(reply_to_string).unwrap_or_default();
+    record_run(
+        ctx,
+        &schedule_key,
+        &task,
+        &task_id,
+        now,
+        Outcome::Success,
+        &reply,
+    );
+    blocked_clients::task_ran(ctx, &schedule_key, &task_id, now, Outcome::Success, &reply);
+    if let Some(on_success) = &task.on_success {
+        run_callback(ctx, &schedule_key, &task_id, on_success, "{reply}", &reply);
+    }
+
+    let key = ctx.open_key_writable(&schedule_key);
+    if let Ok(Some(value)) = key.get_value::<ScheduleDataType>(&SCHEDULE_DATA_TYPE) {
+        release_dependents(ctx, &schedule_key, value, &task_id);
+    }
+    Ok(RedisValue::Null)
+}
+
+///
+/// Applies the misfire policy of a task (or else its schedule's) due at `now`
+///
+/// Returns the outcome of a run that won't execute (SKIPPED, or the error
+/// it was dead-lettered with), or None if it executes
+///
+fn handle_misfire(
+    ctx: &Context,
+    schedule_key: &str,
+    task_id: &str,
+    now: u64,
+) -> Option<RedisResult> {
+    let (task, misfire, next_task, dead_letter) = {
+        let key = ctx.open_key_writable(schedule_key);
+        let value = match key.get_value::<ScheduleDataType>(&SCHEDULE_DATA_TYPE) {
+            Ok(Some(value)) => value,
+            _ => return None,
+        };
+        let task = value.get_task(task_id)?.clone();
+        let misfire = task
+            .misfire
+            .clone()
+            .or_else(|| value.config.misfire.clone())?;
+        let late_by = now.saturating_sub(task.timestamp);
+        match misfire {
+            Misfire::RunAll => return None,
+            Misfire::RunLatestOnly => {
+                // Run once now, the next run follows this one
+                if task.missed_runs(now) {
+                    let task = Task {
+                        timestamp: now,
+                        ..task
+                    };
+                    replicate_task(ctx, schedule_key, task_id, &task);
+                    value.insert_task(task_id.to_string(), task);
+                }
+                return None;
+            }
+            Misfire::SkipIfLateBy(max_late_by) | Misfire::DeadLetterIfLateBy(max_late_by)
+                if late_by <= max_late_by =>
+            {
+                return None
+            }
+            _ => {}
+        }
+
+        let next_task = finish_task_run(ctx, schedule_key, task_id, &task, value, now);
+        (task, misfire, next_task, value.config.dead_letter.clone())
+    };
+
+    let error_msg = format!(
+        "ERR the task was late by {} ms",
+        now.saturating_sub(task.timestamp)
+    );
+    let msg = format!(
+        "Task (key={}, id={}) missed its run: {}",
+        schedule_key, task_id, error_msg
+    );
+    ctx.log_notice(&msg);
+    if next_task.is_none() {
+        cancel_dependents(ctx, schedule_key, task_id, &error_msg).unwrap_or_else(|cancel_error| {
+            let msg = format!(
+                "Failed to cancel the tasks waiting for task (key={}, id={}); Error={:#?}",
+                schedule_key, task_id, cancel_error
+            );
+            ctx.log_warning(&msg);
+        });
+    }
+
+    if let Misfire::SkipIfLateBy(_) = misfire {
+        record_run(
+            ctx,
+            schedule_key,
+            &task,
+            task_id,
+            now,
+            Outcome::Skipped,
+            &error_msg,
+        );
+        blocked_clients::task_ran(
+            ctx,
+            schedule_key,
+            task_id,
+            now,
+            Outcome::Skipped,
+            &error_msg,
+        );
+        return Some(Ok(RedisValue::SimpleStringStatic("SKIPPED")));
+    }
+
+    record_run(
+        ctx,
+        schedule_key,
+        &task,
+        task_id,
+        now,
+        Outcome::Failure,
+        &error_msg,
+    );
+    blocked_clients::task_ran(
+        ctx,
+        schedule_key,
+        task_id,
+        now,
+        Outcome::Failure,
+        &error_msg,
+    );
+    if let Some(dead_letter) = &dead_letter {
+        dead_letter_task(ctx, schedule_key, task_id, &task, &error_msg, dead_letter)
+            .unwrap_or_else(|dead_letter_error| {
+                let msg = format!(
+                    "Failed to dead-letter task (key={}, id={}, target={:?}); Error={:#?}",
+                    schedule_key, task_id, dead_letter, dead_letter_error
+                );
+                ctx.log_warning(&msg);
+            });
+    }
+    Some(Err(RedisError::String(error_msg)))
+}
+
+///
+/// SCHEDULE.EXEC key task-id
+///
+/// Returns SKIPPED if the task's guard doesn't hold
+///
+pub fn exec(ctx: &Context, args: Vec<String>) -> RedisResult {
+    ctx.log_notice(format!("{:?}", args).as_str());
+
+    let mut args = args.iter().skip(1);
+    let schedule_key = args.next_string()?;
+    let task_id = args.next_string()?;
+
+    let result = execute_schedule_task(ctx, schedule_key.clone(), task_id);
+    open_key_and_update_timer(&ctx, schedule_key, None);
+
+    result
+}
+
+// The keys of a transaction's commands as they were before it ran:
+// (key, DUMP payload or None if it didn't exist, TTL in milliseconds or zero)
+type Snapshot = Vec<(String, Option<Vec<u8>>, i64)>;
+
+// Whether the command can't change its keys (COMMAND INFO flags it as `readonly`).
+// Scripts aren't flagged as `write`, but they may write their keys
+fn is_readonly_command(ctx: &Context, command: &[String]) -> Result<bool, RedisError> {
+    match ctx.call("COMMAND", &["INFO", &command[0]])? {
+        RedisValue::Array(infos) => match infos.first() {
+            Some(RedisValue::Array(info)) => Ok(matches!(
+                info.get(2),
+                Some(RedisValue::Array(flags)) if flags.iter().any(|flag| {
+                    matches!(flag, RedisValue::SimpleString(flag) if flag == "readonly")
+                })
+            )),
+            _ => Err(RedisError::String(format!(
+                "ERR unknown command '{}'",
+                command[0]
+            ))),
+        },
+        _ => Err(RedisError::Str("ERR unexpected COMMAND INFO reply")),
+    }
+}
+
+// For each command of a transaction, the keys it writes that no command before it
+// wrote: they're snapshotted right before it runs. The keys of read-only commands
+// don't need to be rolled back, and aren't snapshotted.
+// Fails if a command can't run (e.g. an unknown command), as `get_command_keys` does
+fn keys_written_first(
+    ctx: &Context,
+    commands: &[Vec<String>],
+) -> Result<Vec<Vec<String>>, RedisError> {
+    let mut written: Vec<&String> = Vec::new();
+    commands
+        .iter()
+        .map(|command| {
+            let keys = ctx.get_command_keys(command)?;
+            let mut written_first = Vec::new();
+            if !is_readonly_command(ctx, command)? {
+                for pos in keys {
+                    let key = &command[pos as usize];
+                    if !written.contains(&key) {
+                        written.push(key);
+                        written_first.push(key.clone());
+                    }
+                }
+            }
+            Ok(written_first)
+        })
+        .collect()
+}
+
+// DUMPs the keys: it costs as much as serializing their values
+fn snapshot_keys(
+    ctx: &Context,
+    keys: &[String],
+    snapshot: &mut Snapshot,
+) -> Result<(), RedisError> {
+    for key in keys {
+        let payload = ctx.dump_key(key)?;
+        let ttl = match ctx.call("PTTL", &[key])? {
+            RedisValue::Integer(ttl) if ttl > 0 => ttl,
+            _ => 0,
+        };
+        snapshot.push((key.clone(), payload, ttl));
+    }
+    Ok(())
+}
+
+fn restore_keys(ctx: &Context, snapshot: &Snapshot) -> Result<(), RedisError> {
+    for (key, payload, ttl) in snapshot {
+        match payload {
+            Some(payload) => ctx.restore_key(key, *ttl, payload)?,
+            None => {
+                ctx.call("DEL", &[key])?;
+            }
+        }
+    }
+    Ok(())
+}
+
+///
+/// SCHEDULE.EXECDUE key (timestamp | PXAT ms)
+///
+/// A failing task doesn't stop the others from executing. Due payload tasks
+/// are delivered if the schedule is configured to.
+/// Returns a summary: executed and failed counts, the failed task ids with their errors,
+/// how many tasks were skipped by their guard and how many are left once the budget is spent
+///
+pub fn exec_due(ctx: &Context, args: Vec<String>) -> RedisResult {
+    ctx.log_notice(format!("{:?}", args).as_str());
+
+    let mut args = args.into_iter().skip(1);
+    let schedule_key = args.next_string()?;
+    let (timestamp, _) = next_timestamp(&mut args)?;
+
+    let mut drain = Drain::new(timers::budget());
+    drain_due_tasks(ctx, schedule_key, timestamp, &mut drain)
+}
+
 ///
-/// Helper function to add a task to a schedule.
-/// If the schedule doesn't exist, it will create it.
+/// Executes the schedule's tasks due at `timestamp`, until the drain's budget is spent
 ///
-/// Replicate a SCHEDULE.REPLICATE command to the AOF
+/// Returns the summary of SCHEDULE.EXECDUE
 ///
-fn add_task_helper_to_schedule(
+pub fn drain_due_tasks(
     ctx: &Context,
     schedule_key: String,
     timestamp: u64,
-    delayed_command: Vec<String>,
-    task_id: Option<String>,
-) -> Result<String, RedisError> {
-    let key = ctx.open_key_writable(&schedule_key);
-    let value = key.get_value::<ScheduleDataType>(&SCHEDULE_DATA_TYPE)?;
-
-    let task_id = task_id.unwrap_or_else(|| Uuid::new_v4().to_hyphenated().to_string());
-
-    match value {
-        Some(value) => {
-            value.add_task(timestamp, task_id.clone(), delayed_command.clone());
+    drain: &mut Drain,
+) -> RedisResult {
+    // Take a snapshot of the due tasks, executing them changes the timetable
+    let due_task_ids = {
+        let key = ctx.open_key_writable(&schedule_key);
+        match key.get_value::<ScheduleDataType>(&SCHEDULE_DATA_TYPE) {
+            Ok(Some(value)) => {
+                release_expired_leases(ctx, &schedule_key, value, timestamp);
+                let mut due_task_ids = value.due_task_ids(timestamp);
+                if value.config.deliver.is_some() {
+                    due_task_ids.extend(value.due_payload_task_ids(timestamp, usize::MAX));
+                }
+                due_task_ids
+            }
+            _ => Vec::new(),
         }
-        None => {
-            let mut value = ScheduleDataType::new();
-            value.add_task(timestamp, task_id.clone(), delayed_command.clone());
-            key.set_value(&SCHEDULE_DATA_TYPE, value)?;
+    };
+    let mut executed = 0;
+    let mut skipped = 0;
+    let mut errors = Vec::new();
+    for task_id in due_task_ids {
+        // The rest stays due, the timer's next tick executes it
+        if drain.exhausted() {
+            break;
+        }
+        drain.spend();
+        let result = match handle_misfire(ctx, &schedule_key, &task_id, timestamp) {
+            Some(result) => result,
+            None => execute_schedule_task(ctx, schedule_key.clone(), task_id.clone()),
+        };
+        match result {
+            Ok(RedisValue::SimpleStringStatic("SKIPPED")) => skipped += 1,
+            Ok(_) => executed += 1,
+            Err(error) => errors.push(RedisValue::from(vec![task_id, error.to_string()])),
+        }
+    }
+
+    let backlog = {
+        let key = ctx.open_key(&schedule_key);
+        match key.get_value::<ScheduleDataType>(&SCHEDULE_DATA_TYPE) {
+            Ok(Some(value)) => value.backlog(timestamp),
+            _ => 0,
         }
     };
+    open_key_and_update_timer(&ctx, schedule_key, None);
+    Ok(RedisValue::Array(vec![
+        RedisValue::from("executed"),
+        RedisValue::Integer(executed),
+        RedisValue::from("failed"),
+        RedisValue::Integer(errors.len() as i64),
+        RedisValue::from("errors"),
+        RedisValue::Array(errors),
+        RedisValue::from("skipped"),
+        RedisValue::Integer(skipped),
+        RedisValue::from("backlog"),
+        RedisValue::Integer(backlog as i64),
+    ]))
+}
 
-    let timestamp_str = timestamp.to_string();
-    let mut replicate_args: Vec<&str> = Vec::with_capacity(3 + delayed_command.len()); // key + ts + id + [command]
-    replicate_args.push(&schedule_key);
-    replicate_args.push(&timestamp_str);
-    replicate_args.push(&task_id);
-    replicate_args.extend(delayed_command.iter().map(|x| x.as_str()));
-    ctx.replicate("SCHEDULE.REPLICATE", &replicate_args);
+///
+/// Removes the (at most `count`) due payload tasks and returns them
+/// as (id, timestamp, payload) entries. Recurring tasks move to their next run
+///
+pub fn pop_due_tasks(
+    ctx: &Context,
+    schedule_key: &str,
+    count: usize,
+) -> Result<Vec<RedisValue>, RedisError> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+    let key = ctx.open_key_writable(schedule_key);
+    let value = match key.get_value::<ScheduleDataType>(&SCHEDULE_DATA_TYPE)? {
+        Some(value) => value,
+        None => return Ok(Vec::new()),
+    };
+    release_expired_leases(ctx, schedule_key, value, now);
 
-    open_key_and_update_timer(&ctx, schedule_key, None);
+    let mut ret = Vec::new();
+    for task_id in value.due_payload_task_ids(now, count) {
+        let task = match value.get_task(&task_id) {
+            Some(task) => task.clone(),
+            None => continue,
+        };
+        finish_task_run(ctx, schedule_key, &task_id, &task, value, now);
+        release_dependents(ctx, schedule_key, value, &task_id);
+        ret.push(RedisValue::Array(vec![
+            RedisValue::BulkString(task_id),
+            RedisValue::BulkString(task.timestamp.to_string()),
+            RedisValue::BulkString(task.payload.unwrap_or_default()),
+        ]));
+    }
+    if !ret.is_empty() {
+        // The tasks that were waiting for them may be due
+        update_timer(
+            ctx,
+            schedule_key.to_string(),
+            value,
+            Duration::from_millis(now),
+        );
+    }
+    Ok(ret)
+}
 
-    Ok(task_id)
+fn next_count<I>(args: &mut I) -> Result<usize, RedisError>
+where
+    I: Iterator<Item = String>,
+{
+    let count = match args.next() {
+        Some(option) if option.eq_ignore_ascii_case("COUNT") => args.next_u64()? as usize,
+        Some(_) => return Err(RedisError::Str("ERR syntax error")),
+        None => 1,
+    };
+    args.done()?;
+    Ok(count)
 }
 
 ///
-/// SCHEDULE.REPLICATE key task_id timestamp CMD...
+/// SCHEDULE.POPDUE key [COUNT n]
 ///
-pub fn replicate(ctx: &Context, args: Vec<String>) -> RedisResult {
+/// Removes and returns the due payload tasks (at most n, 1 by default)
+/// as (id, timestamp, payload) entries. Recurring tasks move to their next run
+///
+pub fn pop_due(ctx: &Context, args: Vec<String>) -> RedisResult {
     ctx.log_notice(format!("{:?}", args).as_str());
 
     let mut args = args.into_iter().skip(1);
     let schedule_key = args.next_string()?;
-    let timestamp = args.next_u64()?;
-    let task_id = args.next_string()?;
-    let delayed_command: Vec<String> = args.collect();
-
-    let command_keys = ctx.get_command_keys(&delayed_command)?;
-    if ctx.is_keys_position_request() {
-        let offset = 4; // (0)SCHEDULE.REPLICATE (1)KEY (2)task_id (3)DELAY [CMD] ==
-        ctx.key_at_pos(1);
-        for key_pos in command_keys {
-            ctx.key_at_pos(offset + key_pos);
-        }
-        return Ok(RedisValue::NoReply);
-    }
+    let count = next_count(&mut args)?;
 
-    let task_id =
-        add_task_helper_to_schedule(ctx, schedule_key, timestamp, delayed_command, Some(task_id))?;
-    Ok(RedisValue::BulkString(task_id))
+    Ok(RedisValue::Array(pop_due_tasks(ctx, &schedule_key, count)?))
 }
 
 ///
-/// SCHEDULE.ADD key delay CMD...
+/// SCHEDULE.BPOPDUE key (timeout | PX ms) [COUNT n]
 ///
-pub fn add(ctx: &Context, args: Vec<String>) -> RedisResult {
+/// Blocking version of SCHEDULE.POPDUE, a timeout of zero blocks forever.
+/// Returns nil on timeout, or right away inside MULTI/Lua if nothing is due
+///
+pub fn bpop_due(ctx: &Context, args: Vec<String>) -> RedisResult {
     ctx.log_notice(format!("{:?}", args).as_str());
-    let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
 
     let mut args = args.into_iter().skip(1);
     let schedule_key = args.next_string()?;
-    let delay = args.next_u64()?;
-    let timestamp = now.as_secs() + delay;
-    let delayed_command: Vec<String> = args.collect();
+    let (timeout, _) = next_duration(&mut args)?;
+    let count = next_count(&mut args)?;
 
-    let command_keys = ctx.get_command_keys(&delayed_command)?;
-    if ctx.is_keys_position_request() {
-        let offset = 3; // (0)SCHEDULE.ADD (1)KEY (2)DELAY [CMD] ==
-        ctx.key_at_pos(1);
-        for key_pos in command_keys {
-            ctx.key_at_pos(offset + key_pos);
-        }
-        return Ok(RedisValue::NoReply);
+    let entries = pop_due_tasks(ctx, &schedule_key, count)?;
+    if !entries.is_empty() {
+        return Ok(RedisValue::Array(entries));
+    }
+    if ctx.is_multi_or_lua() {
+        return Ok(RedisValue::Null);
     }
 
-    let task_id = add_task_helper_to_schedule(ctx, schedule_key, timestamp, delayed_command, None)?;
-    Ok(RedisValue::BulkString(task_id))
+    blocked_clients::block_client(
+        ctx,
+        schedule_key.clone(),
+        count,
+        Duration::from_millis(timeout),
+    );
+    // The schedule's timer wakes up the client once a payload task is due
+    open_key_and_update_timer(ctx, schedule_key, None);
+    Ok(RedisValue::NoReply)
 }
 
 ///
-/// SCHEDULE.REM key task-id
+/// A task's run, as SCHEDULE.WAIT replies it: (outcome, output)
 ///
-pub fn rem(ctx: &Context, args: Vec<String>) -> RedisResult {
+pub fn run_reply(outcome: Outcome, output: &str) -> RedisValue {
+    RedisValue::Array(vec![
+        RedisValue::from(outcome.as_str()),
+        RedisValue::from(output.to_string()),
+    ])
+}
+
+///
+/// SCHEDULE.WAIT key task-id (timeout | PX ms)
+///
+/// Blocks until the task runs and returns its outcome, a timeout of zero blocks forever.
+/// If the task isn't in the schedule, it returns its last run in the history right away.
+/// Returns nil on timeout, if the task is removed without running, or right away inside MULTI/Lua
+///
+pub fn wait(ctx: &Context, args: Vec<String>) -> RedisResult {
     ctx.log_notice(format!("{:?}", args).as_str());
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
 
     let mut args = args.into_iter().skip(1);
-    let key = args.next_string()?;
+    let schedule_key = args.next_string()?;
     let task_id = args.next_string()?;
-    let key = ctx.open_key_writable(&key);
+    let (timeout, _) = next_duration(&mut args)?;
+    args.done()?;
 
-    match key.get_value::<ScheduleDataType>(&SCHEDULE_DATA_TYPE)? {
-        Some(value) => {
-            value.del_task(task_id);
-            ctx.replicate_verbatim();
-            Ok(RedisValue::Null)
+    {
+        let key = ctx.open_key(&schedule_key);
+        let value = match key.get_value::<ScheduleDataType>(&SCHEDULE_DATA_TYPE)? {
+            Some(value) => value,
+            None => return Ok(RedisValue::Null),
+        };
+        if value.get_task(&task_id).is_none() {
+            return Ok(value.last_run(&task_id).map_or(RedisValue::Null, |entry| {
+                run_reply(entry.outcome, &entry.output)
+            }));
         }
-        None => Ok(RedisValue::Null),
     }
+    if ctx.is_multi_or_lua() {
+        return Ok(RedisValue::Null);
+    }
+
+    blocked_clients::block_client_until_run(
+        ctx,
+        schedule_key,
+        task_id,
+        now,
+        Duration::from_millis(timeout),
+    );
+    Ok(RedisValue::NoReply)
 }
 
 ///
-/// Helper function to execute task from a schedule,
-///
-/// This function will propagate two items into the AOF:
-///     1. SCHEDULE.REM (remove this task from the schedule)
-///     2. [COMMAND] (the command this task executed)
+/// SCHEDULE.CLAIM key consumer LEASE (seconds | PX ms) [COUNT n]
 ///
-/// Important: This function will not create/update timers, this
-/// is something that must be handled by the caller
+/// Leases the due payload tasks (at most n, 1 by default) to a consumer and returns
+/// them as (id, timestamp, payload, deliveries) entries. The tasks stay in the
+/// schedule until acknowledged, they are due again once the lease expires
 ///
-fn execute_schedule_task(ctx: &Context, schedule_key: String, task_id: String) -> RedisResult {
-    let key = ctx.open_key_writable(&schedule_key);
+pub fn claim(ctx: &Context, args: Vec<String>) -> RedisResult {
+    ctx.log_notice(format!("{:?}", args).as_str());
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
 
-    if let Some(value) = key.get_value::<ScheduleDataType>(&SCHEDULE_DATA_TYPE)? {
-        let task = value.del_task(task_id.clone());
-        if let Some(task) = task {
-            exec_task(ctx, &task.args).map_err(|error| {
-                let msg = format!(
-                    "Failed to execute task (key={}, id={}, task={:?}); Error={:#?}",
-                    schedule_key, task_id, task, error
-                );
-                ctx.log_warning(&msg);
-                error
-            })?;
+    let mut args = args.into_iter().skip(1);
+    let schedule_key = args.next_string()?;
+    let consumer = args.next_string()?;
+    if !args.next_string()?.eq_ignore_ascii_case("LEASE") {
+        return Err(RedisError::Str("ERR syntax error"));
+    }
+    let lease = match next_duration(&mut args)? {
+        (0, _) => return Err(RedisError::Str("ERR LEASE must be positive")),
+        (lease, _) => lease,
+    };
+    let count = next_count(&mut args)?;
+    let until = valid_timestamp(now.checked_add(lease))?;
 
-            let mut args_iter = task.args.iter();
-            let cmd = args_iter.next().unwrap().as_str();
-            let args: Vec<&str> = args_iter.map(|x| x.as_str()).collect();
-            ctx.replicate("SCHEDULE.REM", &[&schedule_key, &task_id]);
-            ctx.replicate(cmd, &args);
-        }
-        Ok(RedisValue::Null)
-    } else {
-        Ok(RedisValue::Null)
+    let key = ctx.open_key_writable(&schedule_key);
+    let value = match key.get_value::<ScheduleDataType>(&SCHEDULE_DATA_TYPE)? {
+        Some(value) => value,
+        None => return Ok(RedisValue::Array(Vec::new())),
+    };
+    release_expired_leases(ctx, &schedule_key, value, now);
+
+    let mut ret = Vec::new();
+    for task_id in value.due_payload_task_ids(now, count) {
+        let task = match value.lease_task(task_id.clone(), consumer.clone(), until) {
+            Some(task) => task.clone(),
+            None => continue,
+        };
+        replicate_task(ctx, &schedule_key, &task_id, &task);
+        ret.push(RedisValue::Array(vec![
+            RedisValue::BulkString(task_id),
+            RedisValue::BulkString(task.timestamp.to_string()),
+            RedisValue::BulkString(task.payload.unwrap_or_default()),
+            RedisValue::Integer(task.deliveries as i64),
+        ]));
     }
+
+    open_key_and_update_timer(ctx, schedule_key, None);
+    Ok(RedisValue::Array(ret))
 }
 
 ///
-/// SCHEDULE.EXEC key task-id
+/// SCHEDULE.ACK key task-id
 ///
-pub fn exec(ctx: &Context, args: Vec<String>) -> RedisResult {
+/// Removes a claimed task (or moves it to its next run). Returns 1 if
+/// the task was acknowledged, 0 if it isn't claimed
+///
+pub fn ack(ctx: &Context, args: Vec<String>) -> RedisResult {
     ctx.log_notice(format!("{:?}", args).as_str());
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
 
-    let mut args = args.iter().skip(1);
+    let mut args = args.into_iter().skip(1);
     let schedule_key = args.next_string()?;
     let task_id = args.next_string()?;
+    args.done()?;
 
-    execute_schedule_task(ctx, schedule_key.clone(), task_id)?;
-    open_key_and_update_timer(&ctx, schedule_key, None);
+    let key = ctx.open_key_writable(&schedule_key);
+    let value = match key.get_value::<ScheduleDataType>(&SCHEDULE_DATA_TYPE)? {
+        Some(value) => value,
+        None => return Ok(RedisValue::Integer(0)),
+    };
+    let task = match value.get_task(&task_id) {
+        Some(task) if task.lease.is_some() => task.clone(),
+        _ => return Ok(RedisValue::Integer(0)),
+    };
 
-    Ok(RedisValue::Null)
+    finish_task_run(ctx, &schedule_key, &task_id, &task, value, now);
+    release_dependents(ctx, &schedule_key, value, &task_id);
+    open_key_and_update_timer(ctx, schedule_key, None);
+    Ok(RedisValue::Integer(1))
 }
 
 ///
-/// SCHEDULE.EXECDUE key timestamp
+/// SCHEDULE.NACK key task-id
 ///
-pub fn exec_due(ctx: &Context, args: Vec<String>) -> RedisResult {
+/// Releases a claimed task before its lease expires, it is due again right away.
+/// Returns 1 if the task was released, 0 if it isn't claimed
+///
+pub fn nack(ctx: &Context, args: Vec<String>) -> RedisResult {
     ctx.log_notice(format!("{:?}", args).as_str());
 
-    let mut args = args.iter().skip(1);
+    let mut args = args.into_iter().skip(1);
     let schedule_key = args.next_string()?;
-    let timestamp = args.next_u64()?;
+    let task_id = args.next_string()?;
+    args.done()?;
 
-    let key = ctx.open_key(&schedule_key); // Open as read, SCHEDULE.EXEC will open to write
-    let value = key.get_value::<ScheduleDataType>(&SCHEDULE_DATA_TYPE);
-    if let Ok(Some(value)) = value {
-        for (task_timestamp, task_id) in value.timetable_iter() {
-            if *task_timestamp > timestamp {
-                break;
-            }
-            execute_schedule_task(ctx, schedule_key.clone(), task_id.to_string())?;
-        }
+    let key = ctx.open_key_writable(&schedule_key);
+    let released = match key.get_value::<ScheduleDataType>(&SCHEDULE_DATA_TYPE)? {
+        Some(value) => value.release_task(task_id),
+        None => false,
+    };
+    if !released {
+        return Ok(RedisValue::Integer(0));
     }
 
-    open_key_and_update_timer(&ctx, schedule_key, None);
-    Ok(RedisValue::Null)
+    ctx.replicate_verbatim();
+    if blocked_clients::has_blocked_clients(ctx, &schedule_key) {
+        ctx.signal_key_as_ready(&schedule_key);
+    }
+    open_key_and_update_timer(ctx, schedule_key, None);
+    Ok(RedisValue::Integer(1))
 }
 
 ///
@@ -233,7 +2158,189 @@ pub fn scan(ctx: &Context, args: Vec<String>) -> RedisResult {
 }
 
 ///
-/// SCHEDULE.INCRBY KEY TASK-ID SECONDS
+/// SCHEDULE.RECORD key task-id (timestamp | PXAT ms) (timestamp | PXAT ms) outcome output
+///
+/// Adds a run to the schedule's history
+///
+pub fn record(ctx: &Context, args: Vec<String>) -> RedisResult {
+    ctx.log_notice(format!("{:?}", args).as_str());
+
+    let mut args = args.into_iter().skip(1);
+    let schedule_key = args.next_string()?;
+    let task_id = args.next_string()?;
+    let (scheduled, _) = next_timestamp(&mut args)?;
+    let (executed, _) = next_timestamp(&mut args)?;
+    let outcome = Outcome::parse(&args.next_string()?.to_lowercase())
+        .ok_or(RedisError::Str("ERR unknown outcome"))?;
+    let output = args.next_string()?;
+    args.done()?;
+
+    let key = ctx.open_key_writable(&schedule_key);
+    if let Some(value) = key.get_value::<ScheduleDataType>(&SCHEDULE_DATA_TYPE)? {
+        value.record_run(HistoryEntry {
+            task_id,
+            scheduled,
+            executed,
+            outcome,
+            output,
+        });
+        ctx.replicate_verbatim();
+    }
+    Ok(RedisValue::Null)
+}
+
+///
+/// SCHEDULE.HISTORY key [COUNT n]
+///
+/// Returns the last runs, newest first, as (id, scheduled, executed, outcome, output)
+///
+pub fn history(ctx: &Context, args: Vec<String>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let key = args.next_string()?;
+    let count = match args.next() {
+        Some(option) if option.eq_ignore_ascii_case("COUNT") => args.next_u64()? as usize,
+        Some(_) => return Err(RedisError::Str("ERR syntax error")),
+        None => 10,
+    };
+    let key = ctx.open_key(&key);
+
+    match key.get_value::<ScheduleDataType>(&SCHEDULE_DATA_TYPE)? {
+        Some(value) => Ok(RedisValue::Array(
+            value
+                .history(count)
+                .into_iter()
+                .map(|entry| {
+                    RedisValue::Array(vec![
+                        RedisValue::from(entry.task_id.clone()),
+                        RedisValue::from(entry.scheduled.to_string()),
+                        RedisValue::from(entry.executed.to_string()),
+                        RedisValue::from(entry.outcome.as_str()),
+                        RedisValue::from(entry.output.clone()),
+                    ])
+                })
+                .collect(),
+        )),
+        None => Ok(RedisValue::Array(vec![])),
+    }
+}
+
+///
+/// SCHEDULE.RESULT key task-id
+///
+/// Returns the task's last run still in the history
+///
+pub fn result(ctx: &Context, args: Vec<String>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let key = args.next_string()?;
+    let task_id = args.next_string()?;
+    let key = ctx.open_key(&key);
+
+    let entry = match key.get_value::<ScheduleDataType>(&SCHEDULE_DATA_TYPE)? {
+        Some(value) => match value.last_run(&task_id) {
+            Some(entry) => entry.clone(),
+            None => return Ok(RedisValue::Null),
+        },
+        None => return Ok(RedisValue::Null),
+    };
+
+    Ok(RedisValue::Array(vec![
+        RedisValue::from("scheduled"),
+        RedisValue::from(entry.scheduled.to_string()),
+        RedisValue::from("executed"),
+        RedisValue::from(entry.executed.to_string()),
+        RedisValue::from("outcome"),
+        RedisValue::from(entry.outcome.as_str()),
+        RedisValue::from("output"),
+        RedisValue::from(entry.output),
+    ]))
+}
+
+///
+/// SCHEDULE.STATS key
+///
+/// Returns the schedule's task count, its backlog (the due tasks
+/// that wait to be executed) and its next run
+///
+pub fn stats(ctx: &Context, args: Vec<String>) -> RedisResult {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+
+    let mut args = args.into_iter().skip(1);
+    let key = args.next_string()?;
+    let key = ctx.open_key(&key);
+
+    let value = match key.get_value::<ScheduleDataType>(&SCHEDULE_DATA_TYPE)? {
+        Some(value) => value,
+        None => return Ok(RedisValue::Null),
+    };
+    let next_payload_timestamp = match value.config.deliver {
+        Some(_) => value.get_min_payload_timestamp(),
+        None => None,
+    };
+    let next_run = match (value.get_min_timestamp(), next_payload_timestamp) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    };
+
+    Ok(RedisValue::Array(vec![
+        RedisValue::from("tasks"),
+        RedisValue::Integer(value.len() as i64),
+        RedisValue::from("backlog"),
+        RedisValue::Integer(value.backlog(now) as i64),
+        RedisValue::from("next_run"),
+        next_run.map_or(RedisValue::Null, |next_run| {
+            RedisValue::from(next_run.to_string())
+        }),
+    ]))
+}
+
+///
+/// SCHEDULE.GET key task-id
+///
+/// Returns the task's fields as name/value pairs (like HGETALL)
+///
+pub fn get(ctx: &Context, args: Vec<String>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let key = args.next_string()?;
+    let task_id = args.next_string()?;
+    let key = ctx.open_key(&key);
+
+    let value = match key.get_value::<ScheduleDataType>(&SCHEDULE_DATA_TYPE)? {
+        Some(value) => value,
+        None => return Ok(RedisValue::Null),
+    };
+    let task = match value.get_task(&task_id) {
+        Some(task) => task,
+        None => return Ok(RedisValue::Null),
+    };
+
+    Ok(RedisValue::Array(vec![
+        RedisValue::from("timestamp"),
+        RedisValue::from(task.timestamp.to_string()),
+        RedisValue::from("command"),
+        RedisValue::from(task.args.clone()),
+        RedisValue::from("options"),
+        RedisValue::from(task_options(task)),
+        RedisValue::from("attempts"),
+        RedisValue::Integer(task.attempts as i64),
+        RedisValue::from("last_error"),
+        task.last_error
+            .clone()
+            .map_or(RedisValue::Null, RedisValue::BulkString),
+        RedisValue::from("payload"),
+        task.payload
+            .clone()
+            .map_or(RedisValue::Null, RedisValue::BulkString),
+        RedisValue::from("consumer"),
+        task.lease.as_ref().map_or(RedisValue::Null, |lease| {
+            RedisValue::BulkString(lease.consumer.clone())
+        }),
+        RedisValue::from("deliveries"),
+        RedisValue::Integer(task.deliveries as i64),
+    ]))
+}
+
+///
+/// SCHEDULE.INCRBY KEY TASK-ID (SECONDS | PX MILLISECONDS)
 ///
 pub fn incrby(ctx: &Context, args: Vec<String>) -> RedisResult {
     ctx.log_notice(format!("{:?}", args).as_str());
@@ -242,11 +2349,14 @@ pub fn incrby(ctx: &Context, args: Vec<String>) -> RedisResult {
     let mut args = args.into_iter().skip(1);
     let schedule_key = args.next_string()?;
     let task_id = args.next_string()?;
-    let inc_size = args.next_u64()?;
+    let (inc_size, _) = next_duration(&mut args)?;
     let key = ctx.open_key_writable(&schedule_key);
 
     match key.get_value::<ScheduleDataType>(&SCHEDULE_DATA_TYPE)? {
         Some(value) => {
+            if let Some(task) = value.get_task(&task_id) {
+                valid_timestamp(task.timestamp.checked_add(inc_size))?;
+            }
             let new_timestamp = value.incr(task_id, inc_size);
             ctx.replicate_verbatim();
             match new_timestamp {
@@ -262,7 +2372,7 @@ pub fn incrby(ctx: &Context, args: Vec<String>) -> RedisResult {
 }
 
 ///
-/// SCHEDULE.DECRBY KEY TASK-ID SECONDS
+/// SCHEDULE.DECRBY KEY TASK-ID (SECONDS | PX MILLISECONDS)
 ///
 pub fn decrby(ctx: &Context, args: Vec<String>) -> RedisResult {
     ctx.log_notice(format!("{:?}", args).as_str());
@@ -271,11 +2381,14 @@ pub fn decrby(ctx: &Context, args: Vec<String>) -> RedisResult {
     let mut args = args.into_iter().skip(1);
     let schedule_key = args.next_string()?;
     let task_id = args.next_string()?;
-    let inc_size = args.next_u64()?;
+    let (inc_size, _) = next_duration(&mut args)?;
     let key = ctx.open_key_writable(&schedule_key);
 
     match key.get_value::<ScheduleDataType>(&SCHEDULE_DATA_TYPE)? {
         Some(value) => {
+            if let Some(task) = value.get_task(&task_id) {
+                valid_timestamp(task.timestamp.checked_sub(inc_size))?;
+            }
             let new_timestamp = value.decr(task_id, inc_size);
             ctx.replicate_verbatim();
             match new_timestamp {
@@ -289,3 +2402,197 @@ pub fn decrby(ctx: &Context, args: Vec<String>) -> RedisResult {
         None => Ok(RedisValue::Null),
     }
 }
+
+///
+/// SCHEDULE.SETAT KEY TASK-ID (TIMESTAMP | PXAT MILLISECONDS)
+///
+pub fn setat(ctx: &Context, args: Vec<String>) -> RedisResult {
+    ctx.log_notice(format!("{:?}", args).as_str());
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+
+    let mut args = args.into_iter().skip(1);
+    let schedule_key = args.next_string()?;
+    let task_id = args.next_string()?;
+    let (timestamp, _) = next_timestamp(&mut args)?;
+    let key = ctx.open_key_writable(&schedule_key);
+
+    match key.get_value::<ScheduleDataType>(&SCHEDULE_DATA_TYPE)? {
+        Some(value) => {
+            let new_timestamp = value.set_timestamp(task_id, timestamp);
+            ctx.replicate_verbatim();
+            match new_timestamp {
+                Some(new_timestamp) => {
+                    update_timer(&ctx, schedule_key, value, now);
+                    Ok(RedisValue::BulkString(new_timestamp.to_string()))
+                }
+                _ => Ok(RedisValue::Null),
+            }
+        }
+        None => Ok(RedisValue::Null),
+    }
+}
+
+///
+/// SCHEDULE.CONFIG KEY [DEADLETTER (SCHEDULE KEY | STREAM KEY | NONE)]
+///     [DELIVER (STREAM KEY [MAXLEN ~ n] | NONE)] [HISTORY n] [MISFIRE policy]
+///
+/// Without options, it returns the schedule's configuration
+///
+pub fn config(ctx: &Context, args: Vec<String>) -> RedisResult {
+    ctx.log_notice(format!("{:?}", args).as_str());
+
+    let mut args = args.into_iter().skip(1).peekable();
+    let schedule_key = args.next_string()?;
+
+    let mut dead_letter = None;
+    let mut deliver = None;
+    let mut history = None;
+    let mut misfire = None;
+    let mut targets = Vec::new();
+    let mut config_keys = Vec::new();
+    let mut pos = 2; // (0)SCHEDULE.CONFIG (1)KEY [options]
+    while let Some(option) = args.next() {
+        match option.to_uppercase().as_str() {
+            "DEADLETTER" => {
+                let target_type = args.next_string()?.to_uppercase();
+                dead_letter = Some(match target_type.as_str() {
+                    "NONE" => None,
+                    "SCHEDULE" | "STREAM" => {
+                        let target = args.next_string()?;
+                        config_keys.push(pos + 2);
+                        targets.push(target.clone());
+                        pos += 1;
+                        if target_type == "STREAM" {
+                            Some(DeadLetter::Stream(target))
+                        } else if target != schedule_key {
+                            Some(DeadLetter::Schedule(target))
+                        } else {
+                            return Err(RedisError::Str(
+                                "ERR a schedule can't be its own dead-letter",
+                            ));
+                        }
+                    }
+                    _ => return Err(RedisError::Str("ERR syntax error")),
+                });
+                pos += 2;
+            }
+            "DELIVER" => {
+                deliver = Some(match args.next_string()?.to_uppercase().as_str() {
+                    "NONE" => None,
+                    "STREAM" => {
+                        let key = args.next_string()?;
+                        config_keys.push(pos + 2);
+                        targets.push(key.clone());
+                        pos += 1;
+                        let maxlen = match args.peek() {
+                            Some(option) if option.eq_ignore_ascii_case("MAXLEN") => {
+                                args.next();
+                                if args.next_string()? != "~" {
+                                    return Err(RedisError::Str("ERR syntax error"));
+                                }
+                                pos += 3;
+                                Some(args.next_u64()?)
+                            }
+                            _ => None,
+                        };
+                        Some(Deliver::Stream { key, maxlen })
+                    }
+                    _ => return Err(RedisError::Str("ERR syntax error")),
+                });
+                pos += 2;
+            }
+            "HISTORY" => {
+                history = Some(args.next_u64()?);
+                pos += 2;
+            }
+            "MISFIRE" => {
+                let (policy, policy_len) = next_misfire(&mut args)?;
+                misfire = Some(policy);
+                pos += 1 + policy_len;
+            }
+            _ => return Err(RedisError::Str("ERR syntax error")),
+        }
+    }
+
+    if ctx.is_keys_position_request() {
+        ctx.key_at_pos(1);
+        for key_pos in config_keys {
+            ctx.key_at_pos(key_pos);
+        }
+        return Ok(RedisValue::NoReply);
+    }
+    // The timer writes to the targets, they must live on the same node as the schedule
+    let slot = key_hash_slot(&schedule_key);
+    if ctx.is_cluster() && targets.iter().any(|target| key_hash_slot(target) != slot) {
+        return Err(RedisError::Str(
+            "ERR the target keys must be in the same slot as the schedule",
+        ));
+    }
+
+    if pos == 2 {
+        let key = ctx.open_key(&schedule_key);
+        let config = match key.get_value::<ScheduleDataType>(&SCHEDULE_DATA_TYPE)? {
+            Some(value) => value.config.clone(),
+            None => return Ok(RedisValue::Null),
+        };
+        let dead_letter = match config.dead_letter {
+            Some(DeadLetter::Schedule(target)) => {
+                RedisValue::from(vec!["schedule".to_string(), target])
+            }
+            Some(DeadLetter::Stream(target)) => {
+                RedisValue::from(vec!["stream".to_string(), target])
+            }
+            None => RedisValue::Null,
+        };
+        let deliver = match config.deliver {
+            Some(Deliver::Stream { key, maxlen }) => {
+                let mut deliver = vec!["stream".to_string(), key];
+                if let Some(maxlen) = maxlen {
+                    deliver.push("maxlen".to_string());
+                    deliver.push(maxlen.to_string());
+                }
+                RedisValue::from(deliver)
+            }
+            None => RedisValue::Null,
+        };
+        return Ok(RedisValue::Array(vec![
+            RedisValue::from("deadletter"),
+            dead_letter,
+            RedisValue::from("deliver"),
+            deliver,
+            RedisValue::from("history"),
+            RedisValue::Integer(config.history as i64),
+            RedisValue::from("misfire"),
+            config.misfire.as_ref().map_or(RedisValue::Null, |misfire| {
+                RedisValue::from(misfire_args(misfire))
+            }),
+        ]));
+    }
+
+    let key = ctx.open_key_writable(&schedule_key);
+    let value = match key.get_value::<ScheduleDataType>(&SCHEDULE_DATA_TYPE)? {
+        Some(value) => value,
+        None => {
+            key.set_value(&SCHEDULE_DATA_TYPE, ScheduleDataType::new())?;
+            key.get_value::<ScheduleDataType>(&SCHEDULE_DATA_TYPE)?
+                .ok_or(RedisError::Str("ERR failed to create the schedule"))?
+        }
+    };
+    if let Some(dead_letter) = dead_letter {
+        value.config.dead_letter = dead_letter;
+    }
+    if let Some(deliver) = deliver {
+        value.config.deliver = deliver;
+    }
+    if let Some(history) = history {
+        value.config.history = history;
+        value.trim_history();
+    }
+    if let Some(misfire) = misfire {
+        value.config.misfire = Some(misfire);
+    }
+    ctx.replicate_verbatim();
+    // Due payload tasks need a timer once the schedule delivers them
+    open_key_and_update_timer(ctx, schedule_key, None);
+    Ok(RedisValue::SimpleStringStatic("OK"))
+}
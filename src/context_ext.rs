@@ -1,7 +1,10 @@
 use std::{
     collections::HashMap,
     ffi::{CStr, CString},
-    ptr::null,
+    os::raw::{c_char, c_void},
+    ptr::{null, null_mut},
+    slice,
+    time::Duration,
 };
 
 use redis_module::{raw, Context, RedisError, RedisString};
@@ -11,8 +14,36 @@ pub trait ContextExt {
     fn replicate(&self, command: &str, args: &[&str]);
     fn get_command_keys(&self, args: &[String]) -> Result<Vec<i32>, RedisError>;
     fn get_server_info(&self, fields: &[String]) -> Result<HashMap<String, String>, RedisError>;
+    fn is_multi_or_lua(&self) -> bool;
+    fn is_cluster(&self) -> bool;
+    fn is_replicated_or_loading(&self) -> bool;
+    fn block_client_on_keys(
+        &self,
+        keys: &[&str],
+        reply_callback: raw::RedisModuleCmdFunc,
+        timeout_callback: raw::RedisModuleCmdFunc,
+        free_privdata: FreePrivDataFunc,
+        timeout: Duration,
+        privdata: *mut c_void,
+    );
+    fn signal_key_as_ready(&self, key: &str);
+    fn get_blocked_client_private_data(&self) -> *mut c_void;
+    fn create_raw_timer(
+        &self,
+        period: Duration,
+        callback: raw::RedisModuleTimerProc,
+    ) -> raw::RedisModuleTimerID;
+    fn stop_raw_timer(&self, timer_id: raw::RedisModuleTimerID) -> bool;
+    fn get_selected_db(&self) -> i32;
+    fn select_db(&self, db: i32) -> bool;
+    fn subscribe_to_server_event(&self, event_id: u64, callback: raw::RedisModuleEventCallback);
+    fn dump_key(&self, key: &str) -> Result<Option<Vec<u8>>, RedisError>;
+    fn restore_key(&self, key: &str, ttl: i64, payload: &[u8]) -> Result<(), RedisError>;
 }
 
+pub type FreePrivDataFunc =
+    Option<unsafe extern "C" fn(ctx: *mut raw::RedisModuleCtx, privdata: *mut c_void)>;
+
 impl ContextExt for Context {
     fn replicate(&self, command: &str, args: &[&str]) {
         raw::replicate(self.ctx, command, args);
@@ -62,4 +93,158 @@ impl ContextExt for Context {
         }
         Ok(ret)
     }
+
+    fn is_multi_or_lua(&self) -> bool {
+        let flags = unsafe { raw::RedisModule_GetContextFlags.unwrap()(self.ctx) } as u32;
+        flags & (raw::REDISMODULE_CTX_FLAGS_MULTI | raw::REDISMODULE_CTX_FLAGS_LUA) != 0
+    }
+
+    fn is_cluster(&self) -> bool {
+        let flags = unsafe { raw::RedisModule_GetContextFlags.unwrap()(self.ctx) } as u32;
+        flags & raw::REDISMODULE_CTX_FLAGS_CLUSTER != 0
+    }
+
+    fn is_replicated_or_loading(&self) -> bool {
+        let flags = unsafe { raw::RedisModule_GetContextFlags.unwrap()(self.ctx) } as u32;
+        flags & (raw::REDISMODULE_CTX_FLAGS_REPLICATED | raw::REDISMODULE_CTX_FLAGS_LOADING) != 0
+    }
+
+    fn block_client_on_keys(
+        &self,
+        keys: &[&str],
+        reply_callback: raw::RedisModuleCmdFunc,
+        timeout_callback: raw::RedisModuleCmdFunc,
+        free_privdata: FreePrivDataFunc,
+        timeout: Duration,
+        privdata: *mut c_void,
+    ) {
+        let redis_string_keys: Vec<RedisString> = keys
+            .iter()
+            .map(|s| RedisString::create(self.ctx, s))
+            .collect();
+        let mut inner_keys: Vec<*mut raw::RedisModuleString> =
+            redis_string_keys.iter().map(|s| s.inner).collect();
+
+        unsafe {
+            raw::RedisModule_BlockClientOnKeys.unwrap()(
+                self.ctx,
+                reply_callback,
+                timeout_callback,
+                free_privdata,
+                timeout.as_millis() as i64,
+                inner_keys.as_mut_ptr(),
+                inner_keys.len() as i32,
+                privdata,
+            );
+        }
+    }
+
+    fn signal_key_as_ready(&self, key: &str) {
+        let key = RedisString::create(self.ctx, key);
+        unsafe { raw::RedisModule_SignalKeyAsReady.unwrap()(self.ctx, key.inner) };
+    }
+
+    fn get_blocked_client_private_data(&self) -> *mut c_void {
+        unsafe { raw::RedisModule_GetBlockedClientPrivateData.unwrap()(self.ctx) }
+    }
+
+    // Unlike Context::create_timer, the callback gets no data, so
+    // stopping the timer doesn't have anything to free
+    fn create_raw_timer(
+        &self,
+        period: Duration,
+        callback: raw::RedisModuleTimerProc,
+    ) -> raw::RedisModuleTimerID {
+        unsafe {
+            raw::RedisModule_CreateTimer.unwrap()(
+                self.ctx,
+                period.as_millis() as i64,
+                callback,
+                null_mut(),
+            )
+        }
+    }
+
+    fn stop_raw_timer(&self, timer_id: raw::RedisModuleTimerID) -> bool {
+        let mut data: *mut c_void = null_mut();
+        let status = unsafe { raw::RedisModule_StopTimer.unwrap()(self.ctx, timer_id, &mut data) };
+        status == raw::REDISMODULE_OK as i32
+    }
+
+    fn get_selected_db(&self) -> i32 {
+        unsafe { raw::RedisModule_GetSelectedDb.unwrap()(self.ctx) }
+    }
+
+    fn select_db(&self, db: i32) -> bool {
+        let status = unsafe { raw::RedisModule_SelectDb.unwrap()(self.ctx, db) };
+        status == raw::REDISMODULE_OK as i32
+    }
+
+    fn subscribe_to_server_event(&self, event_id: u64, callback: raw::RedisModuleEventCallback) {
+        let event = raw::RedisModuleEvent {
+            id: event_id,
+            dataver: 1,
+        };
+        raw::subscribe_to_server_event(self.ctx, event, callback);
+    }
+
+    // DUMP's payload is binary, Context::call only handles UTF-8 replies
+    fn dump_key(&self, key: &str) -> Result<Option<Vec<u8>>, RedisError> {
+        let command = CString::new("DUMP").unwrap();
+        let fmt = CString::new("b").unwrap();
+        let reply = unsafe {
+            raw::RedisModule_Call.unwrap()(
+                self.ctx,
+                command.as_ptr(),
+                fmt.as_ptr(),
+                key.as_ptr() as *const c_char,
+                key.len(),
+            )
+        };
+        if reply.is_null() {
+            return Err(RedisError::Str("ERR failed to DUMP the key"));
+        }
+        let result = match raw::call_reply_type(reply) {
+            raw::ReplyType::String => {
+                let mut len = 0;
+                let ptr = raw::call_reply_string_ptr(reply, &mut len);
+                Ok(Some(
+                    unsafe { slice::from_raw_parts(ptr as *const u8, len) }.to_vec(),
+                ))
+            }
+            raw::ReplyType::Null => Ok(None),
+            raw::ReplyType::Error => Err(RedisError::String(raw::call_reply_string(reply))),
+            _ => Err(RedisError::Str("ERR unexpected DUMP reply")),
+        };
+        raw::free_call_reply(reply);
+        result
+    }
+
+    fn restore_key(&self, key: &str, ttl: i64, payload: &[u8]) -> Result<(), RedisError> {
+        let command = CString::new("RESTORE").unwrap();
+        let fmt = CString::new("blbc").unwrap();
+        let replace = CString::new("REPLACE").unwrap();
+        let reply = unsafe {
+            raw::RedisModule_Call.unwrap()(
+                self.ctx,
+                command.as_ptr(),
+                fmt.as_ptr(),
+                key.as_ptr() as *const c_char,
+                key.len(),
+                ttl,
+                payload.as_ptr() as *const c_char,
+                payload.len(),
+                replace.as_ptr(),
+            )
+        };
+        if reply.is_null() {
+            return Err(RedisError::Str("ERR failed to RESTORE the key"));
+        }
+        let result = match raw::call_reply_type(reply) {
+            raw::ReplyType::Error => Err(RedisError::String(raw::call_reply_string(reply))),
+            _ => Ok(()),
+        };
+        raw::free_call_reply(reply);
+        result
+    }
 }
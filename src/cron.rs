@@ -0,0 +1,249 @@
+use std::fmt;
+
+const SECONDS_PER_MINUTE: u64 = 60;
+const SECONDS_PER_DAY: u64 = 24 * 60 * SECONDS_PER_MINUTE;
+
+// Give up searching for a match after this many years (e.g. "0 0 30 2 *")
+const MAX_SEARCH_DAYS: u64 = 5 * 366;
+
+#[derive(Debug, PartialEq)]
+pub struct CronError(String);
+
+impl fmt::Display for CronError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ERR invalid cron expression: {}", self.0)
+    }
+}
+
+///
+/// A parsed five-field cron expression (minute hour day-of-month month day-of-week)
+///
+/// Every field accepts `*`, single values, ranges (`a-b`), steps (`*/n`, `a-b/n`)
+/// and comma separated lists of those. Day of week goes from 0 (Sunday) to 7 (Sunday).
+/// All the times are in UTC.
+///
+#[derive(Debug, PartialEq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    // Cron matches either day field when both are restricted
+    days_of_month_restricted: bool,
+    days_of_week_restricted: bool,
+}
+
+impl CronSchedule {
+    pub fn parse(expression: &str) -> Result<Self, CronError> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(CronError(format!(
+                "expected 5 fields, got {}",
+                fields.len()
+            )));
+        }
+
+        let mut days_of_week = parse_field(fields[4], 0, 7)?;
+        // 7 is an alias for Sunday
+        if days_of_week & (1 << 7) != 0 {
+            days_of_week = (days_of_week | 1) & !(1 << 7);
+        }
+
+        Ok(CronSchedule {
+            minutes: parse_field(fields[0], 0, 59)?,
+            hours: parse_field(fields[1], 0, 23)?,
+            days_of_month: parse_field(fields[2], 1, 31)?,
+            months: parse_field(fields[3], 1, 12)?,
+            days_of_week,
+            days_of_month_restricted: fields[2] != "*",
+            days_of_week_restricted: fields[4] != "*",
+        })
+    }
+
+    ///
+    /// Returns the first matching timestamp (in seconds) strictly after `timestamp`
+    ///
+    /// Returns None if the expression doesn't match any date in the next few years
+    ///
+    pub fn next_after(&self, timestamp: u64) -> Option<u64> {
+        let mut candidate = (timestamp / SECONDS_PER_MINUTE + 1) * SECONDS_PER_MINUTE;
+        let limit = candidate + MAX_SEARCH_DAYS * SECONDS_PER_DAY;
+
+        while candidate < limit {
+            let days = candidate / SECONDS_PER_DAY;
+            let (_year, month, day) = civil_from_days(days);
+            let seconds_in_day = candidate % SECONDS_PER_DAY;
+            let hour = seconds_in_day / 3600;
+            let minute = (seconds_in_day % 3600) / SECONDS_PER_MINUTE;
+
+            if !has_bit(self.months, month) || !self.matches_day(day, weekday_from_days(days)) {
+                candidate = (days + 1) * SECONDS_PER_DAY;
+            } else if !has_bit(self.hours, hour) {
+                candidate = days * SECONDS_PER_DAY + (hour + 1) * 3600;
+            } else if !has_bit(self.minutes, minute) {
+                candidate += SECONDS_PER_MINUTE;
+            } else {
+                return Some(candidate);
+            }
+        }
+        None
+    }
+
+    fn matches_day(&self, day_of_month: u64, day_of_week: u64) -> bool {
+        let dom = has_bit(self.days_of_month, day_of_month);
+        let dow = has_bit(self.days_of_week, day_of_week);
+        if self.days_of_month_restricted && self.days_of_week_restricted {
+            dom || dow
+        } else {
+            dom && dow
+        }
+    }
+}
+
+fn has_bit(mask: u64, bit: u64) -> bool {
+    mask & (1 << bit) != 0
+}
+
+fn parse_number(value: &str, min: u64, max: u64) -> Result<u64, CronError> {
+    let number: u64 = value
+        .parse()
+        .map_err(|_| CronError(format!("'{}' is not a number", value)))?;
+    if number < min || number > max {
+        return Err(CronError(format!(
+            "{} is out of range ({}-{})",
+            number, min, max
+        )));
+    }
+    Ok(number)
+}
+
+// Parses a single field into a bitmask
+fn parse_field(field: &str, min: u64, max: u64) -> Result<u64, CronError> {
+    let mut mask = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.find('/') {
+            Some(pos) => {
+                let step = parse_number(&part[pos + 1..], 1, max)?;
+                (&part[..pos], step)
+            }
+            None => (part, 1),
+        };
+
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some(pos) = range.find('-') {
+            let start = parse_number(&range[..pos], min, max)?;
+            let end = parse_number(&range[pos + 1..], min, max)?;
+            if start > end {
+                return Err(CronError(format!("invalid range '{}'", range)));
+            }
+            (start, end)
+        } else {
+            let start = parse_number(range, min, max)?;
+            // "a/n" means from a to the end of the range
+            (start, if step > 1 { max } else { start })
+        };
+
+        let mut value = start;
+        while value <= end {
+            mask |= 1 << value;
+            value += step;
+        }
+    }
+    Ok(mask)
+}
+
+// Converts days since the epoch into a (year, month, day) triple
+// See http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+// 1970-01-01 was a Thursday
+fn weekday_from_days(days: u64) -> u64 {
+    (days + 4) % 7
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2021-03-01T00:00:00Z, a Monday
+    const MONDAY: u64 = 1_614_556_800;
+
+    #[test]
+    fn civil_dates() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(MONDAY / SECONDS_PER_DAY), (2021, 3, 1));
+        assert_eq!(weekday_from_days(MONDAY / SECONDS_PER_DAY), 1);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(CronSchedule::parse("* * * *").is_err());
+        assert!(CronSchedule::parse("60 * * * *").is_err());
+        assert!(CronSchedule::parse("* * 0 * *").is_err());
+        assert!(CronSchedule::parse("5-1 * * * *").is_err());
+        assert!(CronSchedule::parse("*/0 * * * *").is_err());
+        assert!(CronSchedule::parse("a * * * *").is_err());
+    }
+
+    #[test]
+    fn every_five_minutes() {
+        let schedule = CronSchedule::parse("*/5 * * * *").unwrap();
+        assert_eq!(schedule.next_after(MONDAY), Some(MONDAY + 300));
+        assert_eq!(schedule.next_after(MONDAY + 1), Some(MONDAY + 300));
+        assert_eq!(schedule.next_after(MONDAY + 300), Some(MONDAY + 600));
+    }
+
+    #[test]
+    fn daily_at_nine() {
+        let schedule = CronSchedule::parse("0 9 * * *").unwrap();
+        assert_eq!(schedule.next_after(MONDAY), Some(MONDAY + 9 * 3600));
+        assert_eq!(
+            schedule.next_after(MONDAY + 9 * 3600),
+            Some(MONDAY + SECONDS_PER_DAY + 9 * 3600)
+        );
+    }
+
+    #[test]
+    fn weekdays() {
+        // Fridays and Sundays (as 7)
+        let schedule = CronSchedule::parse("30 8 * * 5,7").unwrap();
+        let friday = MONDAY + 4 * SECONDS_PER_DAY;
+        let sunday = MONDAY + 6 * SECONDS_PER_DAY;
+        assert_eq!(schedule.next_after(MONDAY), Some(friday + 8 * 3600 + 1800));
+        assert_eq!(
+            schedule.next_after(friday + 9 * 3600),
+            Some(sunday + 8 * 3600 + 1800)
+        );
+    }
+
+    #[test]
+    fn day_of_month_or_day_of_week() {
+        // 15th of the month or any Tuesday
+        let schedule = CronSchedule::parse("0 0 15 * 2").unwrap();
+        let tuesday = MONDAY + SECONDS_PER_DAY;
+        assert_eq!(schedule.next_after(MONDAY), Some(tuesday));
+        assert_eq!(
+            schedule.next_after(MONDAY + 13 * SECONDS_PER_DAY),
+            Some(MONDAY + 14 * SECONDS_PER_DAY)
+        );
+    }
+
+    #[test]
+    fn never_matches() {
+        let schedule = CronSchedule::parse("0 0 30 2 *").unwrap();
+        assert_eq!(schedule.next_after(MONDAY), None);
+    }
+}
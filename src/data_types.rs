@@ -1,30 +1,386 @@
+use crate::cron::CronSchedule;
 use crate::skiplist_ext::{de_skiplist, ser_skiplist};
 
 use redis_module::native_types::RedisType;
 use redis_module::raw;
 use serde::{Deserialize, Serialize};
-use skiplist::ordered_skiplist::Iter;
 use skiplist::OrderedSkipList;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::os::raw::{c_int, c_void};
+use std::time::{Duration, Instant};
 use std::vec::Vec;
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Recurrence {
+    // Five-field cron expression
+    Cron(String),
+    // Interval (in milliseconds) between the end of a run and the next one
+    Every(u64),
+}
+
+impl Recurrence {
+    /// Timestamp of the run following the one scheduled at `timestamp` and executed at `now`
+    pub fn next_run(&self, timestamp: u64, now: u64) -> Option<u64> {
+        match self {
+            Recurrence::Cron(expression) => CronSchedule::parse(expression)
+                .ok()?
+                .next_after(timestamp / 1000)
+                .map(|x| x * 1000),
+            Recurrence::Every(interval) => Some(now + interval),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    // How many times a failed run is retried
+    pub retries: u64,
+    // Delay (in milliseconds) before the first retry, doubled on every attempt
+    pub backoff: u64,
+    // Upper bound (in milliseconds) for the delay between retries
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_backoff: Option<u64>,
+}
+
+impl RetryPolicy {
+    /// Delay before retrying the given failed attempt (starting at 1)
+    pub fn delay(&self, attempt: u64) -> u64 {
+        let exponent = attempt.saturating_sub(1).min(u32::MAX as u64) as u32;
+        let delay = self.backoff.saturating_mul(2u64.saturating_pow(exponent));
+        match self.max_backoff {
+            Some(max_backoff) => delay.min(max_backoff),
+            None => delay,
+        }
+    }
+}
+
+fn is_zero(value: &u64) -> bool {
+    *value == 0
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Lease {
+    // The worker that claimed the task
+    pub consumer: String,
+    // Unix timestamp in milliseconds when the task goes back to the timetable
+    pub until: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Guard {
+    // The key must exist when the task is due
+    Exists(String),
+    // The key must not exist when the task is due
+    NotExists(String),
+    // The key must hold the value when the task is due
+    Eq(String, String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Misfire {
+    // Overdue runs execute anyway (the default)
+    RunAll,
+    // A recurring task that missed runs only executes once, its next run follows it
+    RunLatestOnly,
+    // Runs late by more than this (in milliseconds) are skipped
+    SkipIfLateBy(u64),
+    // Runs late by more than this (in milliseconds) go to the dead-letter target
+    DeadLetterIfLateBy(u64),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Task {
+    // Unix timestamp in milliseconds
     pub timestamp: u64,
     pub args: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recurrence: Option<Recurrence>,
+    // How many runs are left (including the next one), None for unlimited
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remaining_runs: Option<u64>,
+    // Last timestamp a recurring task can run at
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry: Option<RetryPolicy>,
+    // Failed attempts of the current run
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub attempts: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    // Payload tasks are consumed by workers (SCHEDULE.POPDUE) instead of executing a command
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload: Option<String>,
+    // Set while a worker holds the task (SCHEDULE.CLAIM)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lease: Option<Lease>,
+    // How many times the current run was claimed
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub deliveries: u64,
+    // The args hold several `;`-separated commands, executed together (SCHEDULE.ADDMULTI)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub transaction: bool,
+    // The run is skipped unless the condition holds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub guard: Option<Guard>,
+    // Command executed after a successful run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_success: Option<Vec<String>>,
+    // Command executed after a run failed for the last time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_failure: Option<Vec<String>>,
+    // Tasks that must run successfully before this one can run
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub after: Vec<String>,
+    // What to do with an overdue run, instead of the schedule's policy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub misfire: Option<Misfire>,
+    // A dead-lettered run, it only executes by hand (SCHEDULE.EXEC)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub parked: bool,
+}
+
+impl Task {
+    pub fn new(timestamp: u64, args: Vec<String>) -> Self {
+        Task {
+            timestamp,
+            args,
+            recurrence: None,
+            remaining_runs: None,
+            until: None,
+            retry: None,
+            attempts: 0,
+            last_error: None,
+            payload: None,
+            lease: None,
+            deliveries: 0,
+            transaction: false,
+            guard: None,
+            on_success: None,
+            on_failure: None,
+            after: Vec::new(),
+            misfire: None,
+            parked: false,
+        }
+    }
+
+    /// Whether a recurring task missed runs: the one after this run is already due at `now`
+    pub fn missed_runs(&self, now: u64) -> bool {
+        let next_timestamp = self
+            .recurrence
+            .as_ref()
+            .and_then(|recurrence| recurrence.next_run(self.timestamp, now));
+        matches!(next_timestamp, Some(next_timestamp) if next_timestamp <= now)
+    }
+
+    ///
+    /// The commands the task executes: its command, or each command of its transaction
+    ///
+    pub fn commands(&self) -> Vec<&[String]> {
+        if self.transaction {
+            self.args.split(|arg| arg == ";").collect()
+        } else {
+            vec![&self.args]
+        }
+    }
+
+    ///
+    /// Returns the same task, rescheduled to its next run
+    ///
+    /// Returns None if it isn't a recurring task or if it ran for the last time
+    ///
+    pub fn next_run(&self, now: u64) -> Option<Task> {
+        let remaining_runs = match self.remaining_runs {
+            Some(runs) if runs <= 1 => return None,
+            Some(runs) => Some(runs - 1),
+            None => None,
+        };
+        let timestamp = self.recurrence.as_ref()?.next_run(self.timestamp, now)?;
+        if matches!(self.until, Some(until) if timestamp > until) {
+            return None;
+        }
+        Some(Task {
+            timestamp,
+            remaining_runs,
+            attempts: 0,
+            lease: None,
+            deliveries: 0,
+            ..self.clone()
+        })
+    }
+
+    ///
+    /// Returns the same task, rescheduled to retry a failed run
+    ///
+    /// Returns None if the task has no retry policy or if it ran out of attempts.
+    /// Recurring tasks aren't retried, their next run already took the run's place
+    ///
+    pub fn retry(&self, now: u64, error: String) -> Option<Task> {
+        if self.recurrence.is_some() {
+            return None;
+        }
+        let retry = self.retry.as_ref()?;
+        if self.attempts >= retry.retries {
+            return None;
+        }
+        let attempts = self.attempts + 1;
+        Some(Task {
+            timestamp: now.saturating_add(retry.delay(attempts)),
+            attempts,
+            last_error: Some(error),
+            ..self.clone()
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DeadLetter {
+    // Failed tasks are added to another schedule
+    Schedule(String),
+    // Failed tasks are appended to a stream
+    Stream(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Deliver {
+    // Due payload tasks are appended to a stream, trimmed to about `maxlen` entries
+    Stream { key: String, maxlen: Option<u64> },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Outcome {
+    Success,
+    Failure,
+    // The task's guard didn't hold
+    Skipped,
+}
+
+impl Outcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Outcome::Success => "success",
+            Outcome::Failure => "failure",
+            Outcome::Skipped => "skipped",
+        }
+    }
+
+    pub fn parse(outcome: &str) -> Option<Outcome> {
+        match outcome {
+            "success" => Some(Outcome::Success),
+            "failure" => Some(Outcome::Failure),
+            "skipped" => Some(Outcome::Skipped),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub task_id: String,
+    // Unix timestamps in milliseconds when the run was due and when it ran
+    pub scheduled: u64,
+    pub executed: u64,
+    pub outcome: Outcome,
+    // The (truncated) reply or error
+    pub output: String,
+}
+
+// Limits what a tick of the timer executes, across all the due schedules
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Budget {
+    // How many due tasks a tick executes at most, zero means no limit
+    pub max_tasks: u64,
+    // How long (in microseconds) a tick runs at most, zero means no limit
+    pub max_micros: u64,
+}
+
+impl Default for Budget {
+    fn default() -> Self {
+        Budget {
+            max_tasks: 1_000,
+            max_micros: 50_000,
+        }
+    }
+}
+
+impl Budget {
+    /// Whether a drain that executed `tasks` tasks in `elapsed` must yield
+    pub fn exceeded(&self, tasks: u64, elapsed: Duration) -> bool {
+        (self.max_tasks > 0 && tasks >= self.max_tasks)
+            || (self.max_micros > 0 && elapsed.as_micros() >= self.max_micros as u128)
+    }
+}
+
+/// A budget being spent by a drain of the due tasks
+pub struct Drain {
+    budget: Budget,
+    started: Instant,
+    tasks: u64,
+}
+
+impl Drain {
+    pub fn new(budget: Budget) -> Self {
+        Drain {
+            budget,
+            started: Instant::now(),
+            tasks: 0,
+        }
+    }
+
+    pub fn spend(&mut self) {
+        self.tasks += 1;
+    }
+
+    pub fn exhausted(&self) -> bool {
+        self.budget.exceeded(self.tasks, self.started.elapsed())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScheduleConfig {
+    // Where tasks go once they fail for the last time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dead_letter: Option<DeadLetter>,
+    // Where due payload tasks go, instead of waiting for a worker
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deliver: Option<Deliver>,
+    // How many runs the history keeps, it's disabled when zero
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub history: u64,
+    // What to do with overdue runs, unless the task has its own policy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub misfire: Option<Misfire>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ScheduleDataType {
-    // (Timestamp, TaskID)
+    // (Timestamp, TaskID) of the tasks with a command
     #[serde(serialize_with = "ser_skiplist", deserialize_with = "de_skiplist")]
     timetable: OrderedSkipList<(u64, String)>,
+    // (Timestamp, TaskID) of the payload tasks
+    #[serde(
+        default = "OrderedSkipList::new",
+        serialize_with = "ser_skiplist",
+        deserialize_with = "de_skiplist"
+    )]
+    payload_timetable: OrderedSkipList<(u64, String)>,
+    // (Lease expiration, TaskID) of the claimed payload tasks
+    #[serde(
+        default = "OrderedSkipList::new",
+        serialize_with = "ser_skiplist",
+        deserialize_with = "de_skiplist"
+    )]
+    leases: OrderedSkipList<(u64, String)>,
     // TaskID : ARGV[...]
     tasks: HashMap<String, Task>,
-
-    #[serde(skip)]
-    pub timer_id: Option<u64>,
+    // TaskID : IDs of the tasks waiting for it
+    #[serde(default)]
+    dependents: HashMap<String, Vec<String>>,
+    // The last runs, oldest first
+    #[serde(default, skip_serializing_if = "VecDeque::is_empty")]
+    history: VecDeque<HistoryEntry>,
+    #[serde(default)]
+    pub config: ScheduleConfig,
 }
 
 impl ScheduleDataType {
@@ -33,23 +389,136 @@ impl ScheduleDataType {
         ScheduleDataType {
             tasks: HashMap::new(),
             timetable,
-            timer_id: None,
+            payload_timetable: OrderedSkipList::new(),
+            leases: OrderedSkipList::new(),
+            dependents: HashMap::new(),
+            history: VecDeque::new(),
+            config: ScheduleConfig::default(),
         }
     }
 
-    pub fn add_task(&mut self, timestamp: u64, task_id: String, args: Vec<String>) {
-        self.timetable.insert((timestamp, task_id.clone()));
-        self.tasks.insert(task_id, Task { timestamp, args });
+    #[cfg(test)]
+    fn add_task(&mut self, timestamp: u64, task_id: String, args: Vec<String>) {
+        self.insert_task(task_id, Task::new(timestamp, args));
+    }
+
+    // The timetable a task belongs to and its position in it.
+    // A leased task waits for its lease to expire, a parked task or
+    // a task waiting for other tasks isn't in any timetable
+    fn timetable_entry(
+        &mut self,
+        task: &Task,
+    ) -> Option<(&mut OrderedSkipList<(u64, String)>, u64)> {
+        match (&task.lease, &task.payload) {
+            _ if task.parked || !task.after.is_empty() => None,
+            (Some(lease), _) => Some((&mut self.leases, lease.until)),
+            (None, Some(_)) => Some((&mut self.payload_timetable, task.timestamp)),
+            (None, None) => Some((&mut self.timetable, task.timestamp)),
+        }
+    }
+
+    ///
+    /// Adds a task to the schedule, replacing the task with the same id (if any)
+    ///
+    pub fn insert_task(&mut self, task_id: String, task: Task) {
+        self.del_task(task_id.clone());
+        if let Some((timetable, timestamp)) = self.timetable_entry(&task) {
+            timetable.insert((timestamp, task_id.clone()));
+        }
+        for parent_id in &task.after {
+            let dependents = self.dependents.entry(parent_id.clone()).or_default();
+            if !dependents.contains(&task_id) {
+                dependents.push(task_id.clone());
+            }
+        }
+        self.tasks.insert(task_id, task);
     }
 
     pub fn del_task(&mut self, task_id: String) -> Option<Task> {
         let task = self.tasks.remove(&task_id)?;
-        self.timetable.remove(&(task.timestamp, task_id));
+        for parent_id in &task.after {
+            if let Some(dependents) = self.dependents.get_mut(parent_id) {
+                dependents.retain(|x| x != &task_id);
+                if dependents.is_empty() {
+                    self.dependents.remove(parent_id);
+                }
+            }
+        }
+        if let Some((timetable, timestamp)) = self.timetable_entry(&task) {
+            timetable.remove(&(timestamp, task_id));
+        }
         Some(task)
     }
 
+    ///
+    /// Whether the task waits for `parent_id`, directly or through the tasks it waits for
+    ///
+    pub fn waits_for(&self, task_id: &str, parent_id: &str) -> bool {
+        let mut task_ids = vec![task_id];
+        let mut visited = HashSet::new();
+        while let Some(task_id) = task_ids.pop() {
+            if !visited.insert(task_id) {
+                continue;
+            }
+            if let Some(task) = self.tasks.get(task_id) {
+                if task.after.iter().any(|x| x == parent_id) {
+                    return true;
+                }
+                task_ids.extend(task.after.iter().map(|x| x.as_str()));
+            }
+        }
+        false
+    }
+
+    ///
+    /// A task ran successfully, the tasks waiting for it stop waiting
+    /// (and run once they don't wait for any other task)
+    ///
+    /// Returns the ids of the tasks that were waiting for it
+    ///
+    pub fn complete_dependency(&mut self, parent_id: &str) -> Vec<String> {
+        let dependents = self.dependents.remove(parent_id).unwrap_or_default();
+        for task_id in &dependents {
+            if let Some(mut task) = self.del_task(task_id.clone()) {
+                task.after.retain(|x| x != parent_id);
+                self.insert_task(task_id.clone(), task);
+            }
+        }
+        dependents
+    }
+
+    ///
+    /// A task won't run successfully, the tasks waiting for it
+    /// (and the ones waiting for them) are removed
+    ///
+    /// Returns the removed tasks
+    ///
+    pub fn cancel_dependents(&mut self, parent_id: &str) -> Vec<(String, Task)> {
+        let mut cancelled = Vec::new();
+        let mut parent_ids = vec![parent_id.to_string()];
+        while let Some(parent_id) = parent_ids.pop() {
+            for task_id in self.dependents.remove(&parent_id).unwrap_or_default() {
+                if let Some(task) = self.del_task(task_id.clone()) {
+                    parent_ids.push(task_id.clone());
+                    cancelled.push((task_id, task));
+                }
+            }
+        }
+        cancelled
+    }
+
+    /// When the timer must fire: the next task with a command or the next lease expiration
     pub fn get_min_timestamp(&self) -> Option<u64> {
-        self.timetable
+        let next_task = self.timetable.front().map(|(timestamp, _)| *timestamp);
+        let next_lease = self.leases.front().map(|(until, _)| *until);
+        match (next_task, next_lease) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    pub fn get_min_payload_timestamp(&self) -> Option<u64> {
+        self.payload_timetable
             .front()
             .map(|(timestamp, _task_id)| *timestamp)
     }
@@ -66,26 +535,130 @@ impl ScheduleDataType {
         Some((head_timestamp, head_task_id, task.args))
     }
 
-    pub fn timetable_iter(&self) -> Iter<(u64, String)> {
-        self.timetable.iter()
+    pub fn get_task(&self, task_id: &str) -> Option<&Task> {
+        self.tasks.get(task_id)
     }
 
-    #[cfg(test)]
-    fn len(&self) -> usize {
+    /// Moves a recurring task to its next run
+    pub fn advance_task(
+        &mut self,
+        task_id: String,
+        timestamp: u64,
+        remaining_runs: Option<u64>,
+    ) -> Option<u64> {
+        let mut task = self.del_task(task_id.clone())?;
+        task.timestamp = timestamp;
+        task.remaining_runs = remaining_runs;
+        task.attempts = 0;
+        task.lease = None;
+        task.deliveries = 0;
+        self.insert_task(task_id, task);
+        Some(timestamp)
+    }
+
+    ///
+    /// Pushes a task back to `timestamp` and replaces its command,
+    /// its current run starts over (used to debounce a task)
+    ///
+    /// The task keeps its options (recurrence, retry policy, guard, callbacks, AFTER
+    /// and misfire policy). A dead-lettered task is parked no more, it runs again
+    ///
+    pub fn rearm_task(
+        &mut self,
+        task_id: String,
+        timestamp: u64,
+        args: Vec<String>,
+    ) -> Option<&Task> {
+        let mut task = self.del_task(task_id.clone())?;
+        task.timestamp = timestamp;
+        task.args = args;
+        task.payload = None;
+        task.transaction = false;
+        task.attempts = 0;
+        task.last_error = None;
+        task.lease = None;
+        task.deliveries = 0;
+        task.parked = false;
+        self.insert_task(task_id.clone(), task);
+        self.tasks.get(&task_id)
+    }
+
+    /// Hands a payload task to a consumer until the lease expires
+    pub fn lease_task(&mut self, task_id: String, consumer: String, until: u64) -> Option<&Task> {
+        let mut task = self.del_task(task_id.clone())?;
+        task.lease = Some(Lease { consumer, until });
+        task.deliveries += 1;
+        self.insert_task(task_id.clone(), task);
+        self.tasks.get(&task_id)
+    }
+
+    /// Puts a leased task back into the timetable. Returns false if it isn't leased
+    pub fn release_task(&mut self, task_id: String) -> bool {
+        match self.tasks.get(&task_id) {
+            Some(task) if task.lease.is_some() => {}
+            _ => return false,
+        }
+        if let Some(mut task) = self.del_task(task_id.clone()) {
+            task.lease = None;
+            self.insert_task(task_id, task);
+        }
+        true
+    }
+
+    /// Ids of the tasks whose lease expired at `timestamp`
+    pub fn expired_lease_ids(&self, timestamp: u64) -> Vec<String> {
+        self.leases
+            .iter()
+            .take_while(|(until, _)| *until <= timestamp)
+            .map(|(_, task_id)| task_id.clone())
+            .collect()
+    }
+
+    /// Ids of the tasks with a command due at `timestamp`, in execution order
+    pub fn due_task_ids(&self, timestamp: u64) -> Vec<String> {
+        self.timetable
+            .iter()
+            .take_while(|(task_timestamp, _)| *task_timestamp <= timestamp)
+            .map(|(_, task_id)| task_id.clone())
+            .collect()
+    }
+
+    /// Ids of the (at most `count`) payload tasks due at `timestamp`, in order
+    pub fn due_payload_task_ids(&self, timestamp: u64, count: usize) -> Vec<String> {
+        self.payload_timetable
+            .iter()
+            .take_while(|(task_timestamp, _)| *task_timestamp <= timestamp)
+            .take(count)
+            .map(|(_, task_id)| task_id.clone())
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
         self.tasks.len()
     }
 
+    ///
+    /// How many tasks are due at `timestamp` and wait to be executed
+    /// (or delivered, if the schedule delivers its payload tasks)
+    ///
+    pub fn backlog(&self, timestamp: u64) -> usize {
+        let payload_backlog = match self.config.deliver {
+            Some(_) => self.due_payload_task_ids(timestamp, usize::MAX).len(),
+            None => 0,
+        };
+        self.due_task_ids(timestamp).len() + payload_backlog
+    }
+
     pub fn change_timestamp_by(
         &mut self,
         task_id: String,
         callback: impl Fn(u64) -> u64,
     ) -> Option<u64> {
-        let mut task = self.tasks.get_mut(&task_id)?;
-        self.timetable
-            .remove_first(&(task.timestamp, task_id.clone()));
-        task.timestamp = callback(task.timestamp);
-        self.timetable.insert((task.timestamp, task_id));
-        Some(task.timestamp)
+        let mut task = self.del_task(task_id.clone())?;
+        let new_timestamp = callback(task.timestamp);
+        task.timestamp = new_timestamp;
+        self.insert_task(task_id, task);
+        Some(new_timestamp)
     }
 
     pub fn incr(&mut self, task_id: String, value: u64) -> Option<u64> {
@@ -95,18 +668,72 @@ impl ScheduleDataType {
     pub fn decr(&mut self, task_id: String, value: u64) -> Option<u64> {
         self.change_timestamp_by(task_id, |x| x - value)
     }
+    pub fn set_timestamp(&mut self, task_id: String, timestamp: u64) -> Option<u64> {
+        self.change_timestamp_by(task_id, |_| timestamp)
+    }
 
-    pub fn to_vec(&self) -> Vec<(u64, String, Vec<String>)> {
-        let mut ret = Vec::with_capacity(self.timetable.len());
-        for (timestamp, task_id) in &self.timetable {
-            let final_task_id = task_id.clone();
-            let args = if let Some(task) = self.tasks.get(&final_task_id) {
-                task.args.clone()
-            } else {
-                Vec::new()
-            };
-            ret.push((*timestamp, final_task_id, args));
+    ///
+    /// Converts a schedule saved with second-resolution timestamps (encver 0)
+    ///
+    fn migrate_to_milliseconds(&mut self) {
+        for task in self.tasks.values_mut() {
+            task.timestamp *= 1000;
+            task.until = task.until.map(|x| x * 1000);
+            if let Some(Recurrence::Every(interval)) = &mut task.recurrence {
+                *interval *= 1000;
+            }
+        }
+        self.timetable = self
+            .tasks
+            .iter()
+            .map(|(task_id, task)| (task.timestamp, task_id.clone()))
+            .collect();
+    }
+
+    ///
+    /// Adds a run to the history, dropping the oldest ones beyond the configured length
+    ///
+    pub fn record_run(&mut self, entry: HistoryEntry) {
+        self.history.push_back(entry);
+        self.trim_history();
+    }
+
+    pub fn trim_history(&mut self) {
+        while self.history.len() as u64 > self.config.history {
+            self.history.pop_front();
         }
+    }
+
+    /// Up to `count` runs, newest first
+    pub fn history(&self, count: usize) -> Vec<&HistoryEntry> {
+        self.history.iter().rev().take(count).collect()
+    }
+
+    /// The task's last run still in the history
+    pub fn last_run(&self, task_id: &str) -> Option<&HistoryEntry> {
+        self.history
+            .iter()
+            .rev()
+            .find(|entry| entry.task_id == task_id)
+    }
+
+    ///
+    /// All the tasks (timestamp, id, command) in order. The command
+    /// of a payload task is `PAYLOAD <payload>`
+    ///
+    pub fn to_vec(&self) -> Vec<(u64, String, Vec<String>)> {
+        let mut ret: Vec<(u64, String, Vec<String>)> = self
+            .tasks
+            .iter()
+            .map(|(task_id, task)| {
+                let args = match &task.payload {
+                    Some(payload) => vec!["PAYLOAD".to_string(), payload.clone()],
+                    None => task.args.clone(),
+                };
+                (task.timestamp, task_id.clone(), args)
+            })
+            .collect();
+        ret.sort();
         ret
     }
 }
@@ -118,7 +745,10 @@ unsafe extern "C" fn free(value: *mut c_void) {
 #[allow(non_snake_case, unused)]
 pub extern "C" fn rdb_load(rdb: *mut raw::RedisModuleIO, encver: c_int) -> *mut c_void {
     let data = raw::load_string(rdb);
-    let schedule: ScheduleDataType = serde_json::from_str(&data).unwrap();
+    let mut schedule: ScheduleDataType = serde_json::from_str(&data).unwrap();
+    if encver < 1 {
+        schedule.migrate_to_milliseconds();
+    }
     Box::into_raw(Box::new(schedule)) as *mut c_void
 }
 
@@ -129,7 +759,7 @@ pub extern "C" fn rdb_save(rdb: *mut raw::RedisModuleIO, value: *mut c_void) {
 
 pub static SCHEDULE_DATA_TYPE: RedisType = RedisType::new(
     "schedulet",
-    0,
+    1,
     raw::RedisModuleTypeMethods {
         version: raw::REDISMODULE_TYPE_METHOD_VERSION as u64,
 
@@ -236,15 +866,528 @@ mod tests {
         assert_eq!(schedule.len(), 2);
     }
 
+    #[test]
+    fn set_timestamp() {
+        let mut schedule = ScheduleDataType::new();
+
+        schedule.add_task(10, "task-a".to_string(), vec!["A".to_string()]);
+        schedule.add_task(20, "task-b".to_string(), vec!["B".to_string()]);
+
+        assert_eq!(schedule.set_timestamp("task-b".to_string(), 5), Some(5));
+        assert_eq!(schedule.get_min_timestamp(), Some(5));
+        assert_eq!(schedule.set_timestamp("task-c".to_string(), 5), None);
+        assert_eq!(schedule.len(), 2);
+    }
+
+    #[test]
+    fn due_task_ids() {
+        let mut schedule = ScheduleDataType::new();
+        schedule.add_task(10, "task-a".to_string(), vec![]);
+        schedule.add_task(1, "task-b".to_string(), vec![]);
+        schedule.add_task(100, "task-c".to_string(), vec![]);
+
+        assert_eq!(schedule.due_task_ids(0), Vec::<String>::new());
+        assert_eq!(schedule.due_task_ids(10), vec!["task-b", "task-a"]);
+    }
+
+    #[test]
+    fn next_run() {
+        let mut task = Task::new(1_614_556_800_000, vec!["A".to_string()]);
+        assert_eq!(task.next_run(1_614_556_801_000), None);
+
+        task.recurrence = Some(Recurrence::Cron("*/5 * * * *".to_string()));
+        let next = task.next_run(1_614_556_801_000).unwrap();
+        assert_eq!(next.timestamp, 1_614_556_800_000 + 300_000);
+        assert_eq!(next.args, task.args);
+        assert_eq!(next.recurrence, task.recurrence);
+    }
+
+    #[test]
+    fn next_run_every() {
+        let mut task = Task::new(100, vec!["A".to_string()]);
+        task.recurrence = Some(Recurrence::Every(10));
+        assert_eq!(task.next_run(105).map(|x| x.timestamp), Some(115));
+
+        task.remaining_runs = Some(2);
+        let next = task.next_run(105).unwrap();
+        assert_eq!(next.remaining_runs, Some(1));
+        assert_eq!(next.next_run(115), None);
+
+        task.remaining_runs = None;
+        task.until = Some(120);
+        assert_eq!(task.next_run(105).map(|x| x.timestamp), Some(115));
+        assert_eq!(task.next_run(115), None);
+    }
+
+    #[test]
+    fn missed_runs() {
+        // 2021-03-01 00:00 UTC
+        let mut task = Task::new(1_614_556_800_000, vec!["A".to_string()]);
+        assert!(!task.missed_runs(1_614_560_400_000));
+
+        task.recurrence = Some(Recurrence::Cron("0 * * * *".to_string()));
+        assert!(!task.missed_runs(1_614_556_800_000));
+        assert!(!task.missed_runs(1_614_560_399_000));
+        assert!(task.missed_runs(1_614_560_400_000));
+
+        task.recurrence = Some(Recurrence::Every(1_000));
+        assert!(!task.missed_runs(1_614_560_400_000));
+    }
+
+    #[test]
+    fn payload_tasks() {
+        let mut schedule = ScheduleDataType::new();
+        schedule.add_task(10, "task-a".to_string(), vec!["A".to_string()]);
+        for (timestamp, task_id) in [(1, "task-b"), (5, "task-c"), (20, "task-d")] {
+            schedule.insert_task(
+                task_id.to_string(),
+                Task {
+                    payload: Some(task_id.to_uppercase()),
+                    ..Task::new(timestamp, vec![])
+                },
+            );
+        }
+
+        // Payload tasks don't need a timer
+        assert_eq!(schedule.get_min_timestamp(), Some(10));
+        assert_eq!(schedule.get_min_payload_timestamp(), Some(1));
+        assert_eq!(schedule.due_task_ids(10), vec!["task-a"]);
+        assert_eq!(
+            schedule.due_payload_task_ids(10, 10),
+            vec!["task-b", "task-c"]
+        );
+        assert_eq!(schedule.due_payload_task_ids(10, 1), vec!["task-b"]);
+
+        assert_eq!(schedule.incr("task-b".to_string(), 10), Some(11));
+        assert_eq!(schedule.due_payload_task_ids(10, 10), vec!["task-c"]);
+        assert_eq!(
+            schedule.to_vec()[..2],
+            [
+                (
+                    5,
+                    "task-c".to_string(),
+                    vec!["PAYLOAD".to_string(), "TASK-C".to_string()]
+                ),
+                (10, "task-a".to_string(), vec!["A".to_string()]),
+            ]
+        );
+
+        schedule.del_task("task-c".to_string());
+        assert_eq!(schedule.due_payload_task_ids(10, 10), Vec::<String>::new());
+        assert_eq!(schedule.len(), 3);
+    }
+
+    #[test]
+    fn insert_task_replaces() {
+        let mut schedule = ScheduleDataType::new();
+        schedule.add_task(10, "task-a".to_string(), vec!["A".to_string()]);
+        schedule.add_task(20, "task-a".to_string(), vec!["B".to_string()]);
+
+        assert_eq!(
+            schedule.to_vec(),
+            vec![(20, "task-a".to_string(), vec!["B".to_string()])]
+        );
+    }
+
+    #[test]
+    fn rearm_task() {
+        let mut schedule = ScheduleDataType::new();
+        schedule.add_task(10, "task-a".to_string(), vec!["A".to_string()]);
+        schedule.add_task(20, "task-b".to_string(), vec!["B".to_string()]);
+
+        let task = schedule
+            .rearm_task("task-a".to_string(), 30, vec!["C".to_string()])
+            .unwrap();
+        assert_eq!(task.timestamp, 30);
+        assert_eq!(
+            schedule.to_vec(),
+            vec![
+                (20, "task-b".to_string(), vec!["B".to_string()]),
+                (30, "task-a".to_string(), vec!["C".to_string()]),
+            ]
+        );
+        assert_eq!(schedule.get_min_timestamp(), Some(20));
+        assert!(schedule
+            .rearm_task("task-c".to_string(), 30, vec![])
+            .is_none());
+
+        // A dead-lettered task runs again
+        schedule.insert_task(
+            "task-d".to_string(),
+            Task {
+                parked: true,
+                last_error: Some("ERR".to_string()),
+                ..Task::new(5, vec!["D".to_string()])
+            },
+        );
+        assert_eq!(schedule.get_min_timestamp(), Some(20));
+        let task = schedule
+            .rearm_task("task-d".to_string(), 15, vec!["D".to_string()])
+            .unwrap();
+        assert!(!task.parked);
+        assert_eq!(task.last_error, None);
+        assert_eq!(schedule.get_min_timestamp(), Some(15));
+    }
+
+    #[test]
+    fn commands() {
+        let args = |x: &str| -> Vec<String> { x.split(' ').map(|x| x.to_string()).collect() };
+        let mut task = Task::new(10, args("LREM pending 0 a ; LPUSH ready a ; INCR count"));
+        assert_eq!(task.commands().len(), 1);
+
+        task.transaction = true;
+        assert_eq!(
+            task.commands(),
+            vec![
+                args("LREM pending 0 a").as_slice(),
+                args("LPUSH ready a").as_slice(),
+                args("INCR count").as_slice(),
+            ]
+        );
+    }
+
+    #[test]
+    fn retry_delay() {
+        let mut retry = RetryPolicy {
+            retries: 10,
+            backoff: 100,
+            max_backoff: None,
+        };
+        assert_eq!(retry.delay(1), 100);
+        assert_eq!(retry.delay(2), 200);
+        assert_eq!(retry.delay(4), 800);
+        assert_eq!(retry.delay(100), u64::MAX);
+
+        retry.max_backoff = Some(500);
+        assert_eq!(retry.delay(4), 500);
+    }
+
+    #[test]
+    fn retry() {
+        let mut task = Task::new(100, vec!["A".to_string()]);
+        assert_eq!(task.retry(105, "ERR".to_string()), None);
+
+        task.retry = Some(RetryPolicy {
+            retries: 2,
+            backoff: 10,
+            max_backoff: None,
+        });
+        let first = task.retry(105, "ERR 1".to_string()).unwrap();
+        assert_eq!(first.timestamp, 115);
+        assert_eq!(first.attempts, 1);
+        assert_eq!(first.last_error, Some("ERR 1".to_string()));
+
+        let second = first.retry(115, "ERR 2".to_string()).unwrap();
+        assert_eq!(second.timestamp, 135);
+        assert_eq!(second.attempts, 2);
+        assert_eq!(second.retry(135, "ERR 3".to_string()), None);
+
+        // A recurring task isn't retried, it moves to its next run instead
+        task.recurrence = Some(Recurrence::Every(50));
+        assert_eq!(task.retry(105, "ERR".to_string()), None);
+    }
+
+    #[test]
+    fn advance_task() {
+        let mut schedule = ScheduleDataType::new();
+        schedule.add_task(10, "task-a".to_string(), vec!["A".to_string()]);
+        schedule.add_task(20, "task-b".to_string(), vec!["B".to_string()]);
+
+        assert_eq!(
+            schedule.advance_task("task-a".to_string(), 30, Some(2)),
+            Some(30)
+        );
+        assert_eq!(schedule.get_min_timestamp(), Some(20));
+        assert_eq!(schedule.get_task("task-a").unwrap().remaining_runs, Some(2));
+        assert_eq!(schedule.advance_task("task-c".to_string(), 30, None), None);
+        assert_eq!(schedule.len(), 2);
+    }
+
+    #[test]
+    fn dependencies() {
+        let mut schedule = ScheduleDataType::new();
+        schedule.add_task(10, "task-a".to_string(), vec!["A".to_string()]);
+        schedule.add_task(10, "task-b".to_string(), vec!["B".to_string()]);
+        let after = |parents: &[&str]| parents.iter().map(|x| x.to_string()).collect();
+        schedule.insert_task(
+            "task-c".to_string(),
+            Task {
+                after: after(&["task-a", "task-b"]),
+                ..Task::new(5, vec!["C".to_string()])
+            },
+        );
+        schedule.insert_task(
+            "task-d".to_string(),
+            Task {
+                after: after(&["task-c"]),
+                ..Task::new(5, vec!["D".to_string()])
+            },
+        );
+
+        assert!(schedule.waits_for("task-d", "task-b"));
+        assert!(!schedule.waits_for("task-c", "task-d"));
+
+        // Waiting tasks aren't due
+        assert_eq!(schedule.get_min_timestamp(), Some(10));
+        assert_eq!(schedule.due_task_ids(10), vec!["task-a", "task-b"]);
+
+        assert_eq!(schedule.complete_dependency("task-a"), vec!["task-c"]);
+        assert_eq!(schedule.get_task("task-c").unwrap().after, vec!["task-b"]);
+        assert_eq!(schedule.due_task_ids(10), vec!["task-a", "task-b"]);
+
+        schedule.del_task("task-b".to_string());
+        assert_eq!(schedule.complete_dependency("task-b"), vec!["task-c"]);
+        assert_eq!(schedule.due_task_ids(10), vec!["task-c", "task-a"]);
+
+        let cancelled = schedule.cancel_dependents("task-c");
+        assert_eq!(cancelled.len(), 1);
+        assert_eq!(cancelled[0].0, "task-d");
+        assert_eq!(schedule.len(), 2);
+        assert!(schedule.dependents.is_empty());
+    }
+
+    #[test]
+    fn cancel_dependents() {
+        let mut schedule = ScheduleDataType::new();
+        schedule.add_task(10, "task-a".to_string(), vec!["A".to_string()]);
+        for (task_id, parent_id) in [("task-b", "task-a"), ("task-c", "task-b")] {
+            schedule.insert_task(
+                task_id.to_string(),
+                Task {
+                    after: vec![parent_id.to_string()],
+                    ..Task::new(5, vec![])
+                },
+            );
+        }
+
+        let cancelled: Vec<String> = schedule
+            .cancel_dependents("task-a")
+            .into_iter()
+            .map(|(task_id, _)| task_id)
+            .collect();
+        assert_eq!(cancelled, vec!["task-b", "task-c"]);
+        assert_eq!(schedule.len(), 1);
+        assert!(schedule.dependents.is_empty());
+    }
+
+    #[test]
+    fn leases() {
+        let mut schedule = ScheduleDataType::new();
+        schedule.add_task(50, "task-a".to_string(), vec!["A".to_string()]);
+        for (timestamp, task_id) in [(1, "task-b"), (5, "task-c")] {
+            schedule.insert_task(
+                task_id.to_string(),
+                Task {
+                    payload: Some(task_id.to_uppercase()),
+                    ..Task::new(timestamp, vec![])
+                },
+            );
+        }
+
+        let task = schedule
+            .lease_task("task-b".to_string(), "worker-1".to_string(), 20)
+            .unwrap();
+        assert_eq!(task.deliveries, 1);
+        assert_eq!(task.timestamp, 1);
+        // A leased task isn't due, but its expiration needs a timer
+        assert_eq!(schedule.due_payload_task_ids(10, 10), vec!["task-c"]);
+        assert_eq!(schedule.get_min_timestamp(), Some(20));
+        assert_eq!(schedule.expired_lease_ids(19), Vec::<String>::new());
+        assert_eq!(schedule.expired_lease_ids(20), vec!["task-b"]);
+
+        assert!(schedule.release_task("task-b".to_string()));
+        assert!(!schedule.release_task("task-b".to_string()));
+        assert_eq!(
+            schedule.due_payload_task_ids(10, 10),
+            vec!["task-b", "task-c"]
+        );
+        assert_eq!(schedule.get_min_timestamp(), Some(50));
+
+        // Deliveries add up until the task moves to its next run
+        schedule.lease_task("task-b".to_string(), "worker-2".to_string(), 30);
+        assert_eq!(schedule.get_task("task-b").unwrap().deliveries, 2);
+        schedule.advance_task("task-b".to_string(), 100, None);
+        let task = schedule.get_task("task-b").unwrap();
+        assert_eq!((task.lease.clone(), task.deliveries), (None, 0));
+        assert_eq!(schedule.expired_lease_ids(1000), Vec::<String>::new());
+        assert_eq!(schedule.len(), 3);
+    }
+
+    #[test]
+    fn budget() {
+        let budget = Budget {
+            max_tasks: 0,
+            max_micros: 0,
+        };
+        assert!(!budget.exceeded(1_000, Duration::from_secs(10)));
+
+        let budget = Budget {
+            max_tasks: 10,
+            max_micros: 0,
+        };
+        assert!(!budget.exceeded(9, Duration::from_secs(10)));
+        assert!(budget.exceeded(10, Duration::from_micros(1)));
+
+        let budget = Budget {
+            max_tasks: 0,
+            max_micros: 500,
+        };
+        assert!(!budget.exceeded(1_000, Duration::from_micros(499)));
+        assert!(budget.exceeded(1, Duration::from_micros(500)));
+    }
+
+    #[test]
+    fn drain() {
+        let mut drain = Drain::new(Budget {
+            max_tasks: 2,
+            max_micros: 0,
+        });
+        assert!(!drain.exhausted());
+        drain.spend();
+        assert!(!drain.exhausted());
+        drain.spend();
+        assert!(drain.exhausted());
+    }
+
+    #[test]
+    fn backlog() {
+        let mut schedule = ScheduleDataType::new();
+        schedule.add_task(10, "task-a".to_string(), vec!["A".to_string()]);
+        schedule.add_task(20, "task-b".to_string(), vec!["B".to_string()]);
+        schedule.insert_task(
+            "task-c".to_string(),
+            Task {
+                payload: Some("C".to_string()),
+                ..Task::new(5, vec![])
+            },
+        );
+
+        assert_eq!(schedule.backlog(5), 0);
+        assert_eq!(schedule.backlog(15), 1);
+        assert_eq!(schedule.backlog(20), 2);
+
+        schedule.config.deliver = Some(Deliver::Stream {
+            key: "deliveries".to_string(),
+            maxlen: None,
+        });
+        assert_eq!(schedule.backlog(20), 3);
+    }
+
+    #[test]
+    fn history() {
+        let entry = |task_id: &str, executed: u64| HistoryEntry {
+            task_id: task_id.to_string(),
+            scheduled: 10,
+            executed,
+            outcome: Outcome::Success,
+            output: "OK".to_string(),
+        };
+        let mut schedule = ScheduleDataType::new();
+        schedule.record_run(entry("task-a", 10));
+        assert!(schedule.history(10).is_empty());
+
+        schedule.config.history = 2;
+        schedule.record_run(entry("task-a", 11));
+        schedule.record_run(entry("task-b", 12));
+        schedule.record_run(entry("task-a", 13));
+        assert_eq!(
+            schedule.history(10),
+            vec![&entry("task-a", 13), &entry("task-b", 12)]
+        );
+        assert_eq!(schedule.history(1), vec![&entry("task-a", 13)]);
+        assert_eq!(schedule.last_run("task-b"), Some(&entry("task-b", 12)));
+        assert_eq!(schedule.last_run("task-c"), None);
+
+        schedule.config.history = 1;
+        schedule.trim_history();
+        assert_eq!(schedule.history(10), vec![&entry("task-a", 13)]);
+    }
+
     #[test]
     fn serde() {
         let mut schedule = ScheduleDataType::new();
         schedule.add_task(10, "task-a".to_string(), vec!["A".to_string()]);
         schedule.add_task(6, "task-b".to_string(), vec!["B".to_string()]);
+        schedule.insert_task(
+            "task-c".to_string(),
+            Task {
+                recurrence: Some(Recurrence::Cron("0 * * * *".to_string())),
+                guard: Some(Guard::Eq("status".to_string(), "unpaid".to_string())),
+                on_success: Some(vec!["PUBLISH".to_string(), "done".to_string()]),
+                misfire: Some(Misfire::SkipIfLateBy(60_000)),
+                ..Task::new(60, vec!["C".to_string()])
+            },
+        );
+
+        schedule.insert_task(
+            "task-d".to_string(),
+            Task {
+                payload: Some("D".to_string()),
+                after: vec!["task-a".to_string()],
+                ..Task::new(5, vec![])
+            },
+        );
+        schedule.config.dead_letter = Some(DeadLetter::Stream("dead".to_string()));
+        schedule.config.deliver = Some(Deliver::Stream {
+            key: "deliveries".to_string(),
+            maxlen: Some(1000),
+        });
+        schedule.config.history = 10;
+        schedule.config.misfire = Some(Misfire::RunLatestOnly);
+        schedule.record_run(HistoryEntry {
+            task_id: "task-e".to_string(),
+            scheduled: 10,
+            executed: 12,
+            outcome: Outcome::Failure,
+            output: "ERR failed".to_string(),
+        });
 
         let ser_schedule = serde_json::to_string(&schedule).unwrap();
         let de_schedule: ScheduleDataType = serde_json::from_str(&ser_schedule).unwrap();
         assert_eq!(schedule.tasks, de_schedule.tasks);
         assert_eq!(schedule.timetable, de_schedule.timetable);
+        assert_eq!(schedule.payload_timetable, de_schedule.payload_timetable);
+        assert_eq!(schedule.dependents, de_schedule.dependents);
+        assert_eq!(schedule.config, de_schedule.config);
+        assert_eq!(schedule.history, de_schedule.history);
+    }
+
+    #[test]
+    fn migrate_to_milliseconds() {
+        let mut schedule = ScheduleDataType::new();
+        schedule.add_task(10, "task-a".to_string(), vec!["A".to_string()]);
+        schedule.insert_task(
+            "task-b".to_string(),
+            Task {
+                recurrence: Some(Recurrence::Every(5)),
+                until: Some(20),
+                ..Task::new(6, vec!["B".to_string()])
+            },
+        );
+
+        schedule.migrate_to_milliseconds();
+        assert_eq!(
+            schedule.to_vec(),
+            vec![
+                (6_000, "task-b".to_string(), vec!["B".to_string()]),
+                (10_000, "task-a".to_string(), vec!["A".to_string()]),
+            ]
+        );
+        let task = schedule.get_task("task-b").unwrap();
+        assert_eq!(task.recurrence, Some(Recurrence::Every(5_000)));
+        assert_eq!(task.until, Some(20_000));
+    }
+
+    #[test]
+    fn deserialize_schedule_without_config() {
+        let schedule: ScheduleDataType =
+            serde_json::from_str(r#"{"timetable":[],"tasks":{}}"#).unwrap();
+        assert_eq!(schedule.config, ScheduleConfig::default());
+    }
+
+    #[test]
+    fn deserialize_task_without_recurrence() {
+        let task: Task = serde_json::from_str(r#"{"timestamp":1,"args":["A"]}"#).unwrap();
+        assert_eq!(task, Task::new(1, vec!["A".to_string()]));
     }
 }
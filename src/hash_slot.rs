@@ -0,0 +1,60 @@
+///
+/// The hash slot of a key in a Redis Cluster: the CRC16 of the key modulo 16384.
+/// Only the hash tag (between the first `{` and the next `}`) is hashed, if it isn't empty
+///
+pub fn key_hash_slot(key: &str) -> u16 {
+    let bytes = key.as_bytes();
+    let hashed = match bytes.iter().position(|x| *x == b'{') {
+        Some(start) => match bytes[start + 1..].iter().position(|x| *x == b'}') {
+            Some(len) if len > 0 => &bytes[start + 1..start + 1 + len],
+            _ => bytes,
+        },
+        None => bytes,
+    };
+    crc16(hashed) & 0x3FFF
+}
+
+// CRC16-CCITT (XMODEM), the one Redis Cluster uses
+fn crc16(bytes: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for byte in bytes {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc16_check_value() {
+        assert_eq!(crc16(b"123456789"), 0x31C3);
+    }
+
+    #[test]
+    fn key_hash_slot() {
+        assert_eq!(super::key_hash_slot("foo"), 12182);
+        assert_eq!(
+            super::key_hash_slot("{user1000}.following"),
+            super::key_hash_slot("{user1000}.followers")
+        );
+        assert_eq!(super::key_hash_slot("{user1000}.following"), 3443);
+        // An empty hash tag doesn't count, only the first one does
+        assert_eq!(
+            super::key_hash_slot("foo{}{bar}"),
+            crc16(b"foo{}{bar}") & 0x3FFF
+        );
+        assert_eq!(
+            super::key_hash_slot("foo{{bar}}zap"),
+            crc16(b"{bar") & 0x3FFF
+        );
+    }
+}
@@ -2,17 +2,21 @@
 
 #[macro_use]
 extern crate redis_module;
-use redis_module::{Context, RedisResult};
+use redis_module::{Context, NextArg, RedisError, RedisResult};
 use std::string::String;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use std::vec::Vec;
 
+mod blocked_clients;
 mod context_ext;
 use context_ext::ContextExt;
+mod cron;
 mod data_types;
 use data_types::*;
 mod commands;
+mod hash_slot;
 pub mod skiplist_ext;
+mod timers;
 
 // Extracts the embeded commands from a task and execute it
 //
@@ -26,9 +30,11 @@ fn exec_task(ctx: &Context, task: &[String]) -> RedisResult {
 
 // TODO is this the best way to get whether the node is a replica?
 fn is_replica_node(ctx: &Context) -> bool {
-    let server_info = ctx
-        .get_server_info(&["cluster_enabled".to_string(), "role".to_string()])
-        .unwrap();
+    let server_info =
+        match ctx.get_server_info(&["cluster_enabled".to_string(), "role".to_string()]) {
+            Ok(server_info) => server_info,
+            Err(_) => return false,
+        };
 
     let cluster_enabled = server_info
         .get("cluster_enabled")
@@ -44,8 +50,8 @@ fn is_replica_node(ctx: &Context) -> bool {
     cluster_enabled && role != "master"
 }
 
-// Execute the due tasks and schedule the next execution
-fn exec_due_tasks(ctx: &Context, schedule_key: String) {
+// Execute the due tasks (within the drain's budget) and schedule the next execution
+fn exec_due_tasks(ctx: &Context, schedule_key: String, drain: &mut Drain) {
     // Only execute the task on master nodes
     // It can return without scheduling the next timer
     // because it will schedule when it executes the
@@ -63,82 +69,69 @@ fn exec_due_tasks(ctx: &Context, schedule_key: String) {
     ctx.log_notice(&msg);
 
     let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
-    let now_timestamp = now.as_secs();
-    commands::exec_due(
-        ctx,
-        vec![
-            "SCHEDULE.EXECDUE".to_string(),
-            schedule_key,
-            now_timestamp.to_string(),
-        ],
-    )
-    .unwrap();
+    let result =
+        commands::drain_due_tasks(ctx, schedule_key.clone(), now.as_millis() as u64, drain);
+    match result {
+        Ok(summary) => {
+            let msg = format!(
+                "Executed due tasks for schedule '{}': {:?}",
+                schedule_key, summary
+            );
+            ctx.log_notice(&msg);
+            // Let the clients blocked on SCHEDULE.BPOPDUE pop the due payload tasks
+            if blocked_clients::has_blocked_clients(ctx, &schedule_key) {
+                ctx.signal_key_as_ready(&schedule_key);
+            }
+        }
+        Err(error) => {
+            let msg = format!(
+                "Failed to execute due tasks for schedule '{}'; Error={:#?}",
+                schedule_key, error
+            );
+            ctx.log_warning(&msg);
+            // Make sure the schedule still has a timer
+            open_key_and_update_timer(ctx, schedule_key, None);
+        }
+    }
 }
 
-/// Updates a schedules's timer
+/// Updates when the schedule's due tasks execute next
 ///
-/// If there is no next item, ignore the operation
-/// If the current timer is later then the head, stop current timer
-fn update_timer(
-    ctx: &Context,
-    schedule_key: String,
-    schedule: &mut ScheduleDataType,
-    now: Duration,
-) {
-    let next_timestamp = match schedule.get_min_timestamp() {
-        Some(v) => v,
-        _ => return, // No item in the schedule
-    };
-
-    let next_duration = Duration::from_secs(next_timestamp);
-    let next_duration = if next_duration < now {
-        // Set it to execute ASAP if the task's time is in the past
-        Duration::from_secs(0)
+/// All the schedules share a single timer (see the timers module)
+/// Payload tasks only count while clients are blocked on the schedule,
+/// or if the schedule delivers them
+fn update_timer(ctx: &Context, schedule_key: String, schedule: &ScheduleDataType, now: Duration) {
+    let next_payload_timestamp = if schedule.config.deliver.is_some()
+        || blocked_clients::has_blocked_clients(ctx, &schedule_key)
+    {
+        schedule.get_min_payload_timestamp()
     } else {
-        next_duration - now
+        None
     };
-
-    let create_a_new_timer = if let Some(timer_id) = schedule.timer_id {
-        if let Ok((timer_duration, _timer_arg)) = ctx.get_timer_info::<String>(timer_id) {
-            if timer_duration.as_nanos() == 0 {
-                ctx.log_warning(format!("Found a timer ({}) for schedule '{}'. Its duration is zero, removing a scheduling another one", timer_id, schedule_key).as_str());
-                // ctx.stop_timer::<String>(timer_id).ok();
-                true
-            } else if timer_duration > next_duration {
-                ctx.stop_timer::<String>(timer_id).ok();
-                true
-            } else {
-                // There is a valid timer. Do nothing
-                false
-            }
-        } else {
-            // Tho there is a timer id, I couldn't find it. Create a new one
-            true
-        }
-    } else {
-        // Task has no timer. Create one
-        true
+    let next_timestamp = match (schedule.get_min_timestamp(), next_payload_timestamp) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
     };
 
-    if !create_a_new_timer {
-        return;
-    }
-
-    let new_timer_id = ctx.create_timer(next_duration, exec_due_tasks, schedule_key);
-    schedule.timer_id = Some(new_timer_id);
+    timers::set_next_run(ctx, &schedule_key, next_timestamp, now.as_millis() as u64);
 }
 
 ///
 /// Same as update_timer, but it gets the value from redis
 ///
-/// If now is None, it will get the current system time
+/// If now is None, it will get the current system time.
+/// The timer stops if the key doesn't hold a schedule (anymore)
 fn open_key_and_update_timer(ctx: &Context, schedule_key: String, now: Option<Duration>) {
     let now = now.unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).unwrap());
     let redis_key = ctx.open_key_writable(&schedule_key);
     let redis_value = redis_key.get_value::<ScheduleDataType>(&SCHEDULE_DATA_TYPE);
 
-    if let Ok(Some(value)) = redis_value {
-        update_timer(&ctx, schedule_key, value, now);
+    match redis_value {
+        Ok(Some(value)) => update_timer(&ctx, schedule_key, value, now),
+        _ if timers::has_next_run(ctx, &schedule_key) => {
+            timers::set_next_run(ctx, &schedule_key, None, now.as_millis() as u64)
+        }
+        _ => {}
     }
 }
 
@@ -160,23 +153,110 @@ fn handle_rdb_loading(
     open_key_and_update_timer(ctx, key.to_string(), None);
 }
 
+fn event_adds_key(event_type: redis_module::NotifyEvent, event: &str) -> bool {
+    event_type == redis_module::NotifyEvent::GENERIC && matches!(event, "move_to" | "rename_to")
+}
+
+fn event_removes_key(event_type: redis_module::NotifyEvent, event: &str) -> bool {
+    match event_type {
+        redis_module::NotifyEvent::GENERIC => {
+            matches!(event, "move_from" | "rename_from" | "del")
+        }
+        redis_module::NotifyEvent::EXPIRED => event == "expired",
+        redis_module::NotifyEvent::EVICTED => event == "evicted",
+        _ => false,
+    }
+}
+
+// Keeps the timer in line with the key: a schedule moved or renamed to the key
+// gets its timer, a schedule moved, renamed, deleted or expired from it loses it
+// and releases the clients blocked on it.
+// Only the keys added are opened, the others are looked up in the timers
+fn handle_key_changed(
+    ctx: &Context,
+    event_type: redis_module::NotifyEvent,
+    event: &str,
+    key: &str,
+) {
+    if event_adds_key(event_type, event) {
+        open_key_and_update_timer(ctx, key.to_string(), None);
+    } else if event_removes_key(event_type, event) {
+        if timers::has_next_run(ctx, key) {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+            timers::set_next_run(ctx, key, None, now.as_millis() as u64);
+        }
+        blocked_clients::schedule_removed(ctx, key);
+    }
+}
+
+// Module arguments: [BUDGET max-tasks max-microseconds]
+fn parse_module_args(args: &[String]) -> Result<Budget, RedisError> {
+    let mut budget = Budget::default();
+    let mut args = args.iter().cloned();
+    while let Some(option) = args.next() {
+        match option.to_uppercase().as_str() {
+            "BUDGET" => {
+                budget = Budget {
+                    max_tasks: args.next_u64()?,
+                    max_micros: args.next_u64()?,
+                }
+            }
+            _ => return Err(RedisError::Str("ERR syntax error")),
+        }
+    }
+    Ok(budget)
+}
+
+fn init(ctx: &Context, args: &[String]) -> redis_module::Status {
+    match parse_module_args(args) {
+        Ok(budget) => timers::set_budget(budget),
+        Err(error) => {
+            ctx.log_warning(&format!("Invalid module arguments {:?}: {}", args, error));
+            return redis_module::Status::Err;
+        }
+    }
+    timers::subscribe_to_server_events(ctx);
+    redis_module::Status::Ok
+}
+
 redis_module! {
     name: "ReDelay",
     version: 1,
     data_types: [
         SCHEDULE_DATA_TYPE,
     ],
+    init: init,
     commands: [
         ["schedule.add", commands::add, "write getkeys-api", 1,1,1],
+        ["schedule.addmulti", commands::add_multi, "write getkeys-api", 1,1,1],
+        ["schedule.debounce", commands::debounce, "write getkeys-api", 1,1,1],
+        ["schedule.throttle", commands::throttle, "write getkeys-api", 1,1,1],
+        ["schedule.addat", commands::addat, "write getkeys-api", 1,1,1],
+        ["schedule.cron", commands::cron, "write getkeys-api", 1,1,1],
         ["schedule.exec", commands::exec, "write", 1,1,1],
+        ["schedule.popdue", commands::pop_due, "write", 1,1,1],
+        ["schedule.bpopdue", commands::bpop_due, "write", 1,1,1],
+        ["schedule.wait", commands::wait, "readonly", 1,1,1],
+        ["schedule.claim", commands::claim, "write", 1,1,1],
+        ["schedule.ack", commands::ack, "write", 1,1,1],
+        ["schedule.nack", commands::nack, "write", 1,1,1],
         ["schedule.execdue", commands::exec_due, "write", 1,1,1],
         ["schedule.rem", commands::rem, "write", 1,1,1],
+        ["schedule.advance", commands::advance, "write", 1,1,1],
         ["schedule.replicate", commands::replicate, "write getkeys-api", 1,1,1],
         ["schedule.scan", commands::scan, "readonly", 1,1,1],
+        ["schedule.get", commands::get, "readonly", 1,1,1],
+        ["schedule.stats", commands::stats, "readonly", 1,1,1],
+        ["schedule.history", commands::history, "readonly", 1,1,1],
+        ["schedule.result", commands::result, "readonly", 1,1,1],
+        ["schedule.record", commands::record, "write", 1,1,1],
         ["schedule.incrby", commands::incrby, "write", 1,1,1],
         ["schedule.decrby", commands::decrby, "write", 1,1,1],
+        ["schedule.setat", commands::setat, "write", 1,1,1],
+        ["schedule.config", commands::config, "write getkeys-api", 1,1,1],
     ],
     event_handlers: [
-        [@LOADED @GENERIC: handle_rdb_loading]
+        [@LOADED @GENERIC: handle_rdb_loading],
+        [@GENERIC @EXPIRED @EVICTED: handle_key_changed],
     ]
 }
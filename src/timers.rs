@@ -0,0 +1,401 @@
+use redis_module::{raw, Context};
+use skiplist::OrderedSkipList;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::os::raw::c_void;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::blocked_clients;
+use crate::context_ext::ContextExt;
+use crate::data_types::{Budget, Drain};
+
+thread_local! {
+    // The next run of every schedule, driven by a single Redis timer.
+    // Redis calls the module from its main thread only
+    static TIMERS: RefCell<Timers> = RefCell::new(Timers::new());
+    // Limits each tick of the timer, across all the schedules it executes
+    static BUDGET: Cell<Budget> = Cell::new(Budget::default());
+}
+
+// The Redis timer waits at most this long (in milliseconds), and then is armed again.
+// Redis converts the period to microseconds, a period too long would overflow
+const MAX_TIMER_PERIOD: u64 = 24 * 60 * 60 * 1000;
+
+// A schedule key within the database it lives in
+type ScheduleKey = (i32, String);
+// (Timestamp, database, schedule key) of a schedule's next run
+type NextRun = (u64, i32, String);
+
+struct Timers {
+    timetable: OrderedSkipList<NextRun>,
+    // Schedule key : its timestamp in the timetable
+    next_runs: HashMap<ScheduleKey, u64>,
+    // The Redis timer and when it fires
+    timer: Option<(raw::RedisModuleTimerID, u64)>,
+}
+
+impl Timers {
+    fn new() -> Self {
+        Timers {
+            timetable: OrderedSkipList::new(),
+            next_runs: HashMap::new(),
+            timer: None,
+        }
+    }
+
+    fn set_next_run(&mut self, db: i32, schedule_key: &str, timestamp: Option<u64>) {
+        let key = (db, schedule_key.to_string());
+        if let Some(previous) = self.next_runs.remove(&key) {
+            self.timetable.remove(&(previous, db, key.1.clone()));
+        }
+        if let Some(timestamp) = timestamp {
+            self.timetable.insert((timestamp, db, key.1.clone()));
+            self.next_runs.insert(key, timestamp);
+        }
+    }
+
+    fn has_next_run(&self, db: i32, schedule_key: &str) -> bool {
+        self.next_runs.contains_key(&(db, schedule_key.to_string()))
+    }
+
+    fn next_timestamp(&self) -> Option<u64> {
+        self.timetable.front().map(|(timestamp, _, _)| *timestamp)
+    }
+
+    // Removes and returns the schedules due at `now`
+    fn pop_due(&mut self, now: u64) -> Vec<NextRun> {
+        let mut next_runs = Vec::new();
+        while matches!(self.timetable.front(), Some((timestamp, _, _)) if *timestamp <= now) {
+            if let Some((timestamp, db, schedule_key)) = self.timetable.pop_front() {
+                self.next_runs.remove(&(db, schedule_key.clone()));
+                next_runs.push((timestamp, db, schedule_key));
+            }
+        }
+        next_runs
+    }
+
+    // Puts back the due schedules a tick didn't get to,
+    // unless they got an earlier next run in the meantime
+    fn requeue(&mut self, next_runs: Vec<NextRun>) {
+        for (timestamp, db, schedule_key) in next_runs {
+            match self.next_runs.get(&(db, schedule_key.clone())) {
+                Some(current) if *current <= timestamp => {}
+                _ => self.set_next_run(db, &schedule_key, Some(timestamp)),
+            }
+        }
+    }
+
+    // Drops the schedules of a flushed database (all of them if None)
+    fn flush(&mut self, db: Option<i32>) {
+        let flushed: Vec<ScheduleKey> = self
+            .next_runs
+            .keys()
+            .filter(|(key_db, _)| db.is_none() || db == Some(*key_db))
+            .cloned()
+            .collect();
+        for (db, schedule_key) in flushed {
+            self.set_next_run(db, &schedule_key, None);
+        }
+    }
+
+    // Follows the schedules of two databases swapped by SWAPDB
+    fn swap_dbs(&mut self, first: i32, second: i32) {
+        let swap = |db: i32| match db {
+            db if db == first => second,
+            db if db == second => first,
+            db => db,
+        };
+        let timetable = std::mem::replace(&mut self.timetable, OrderedSkipList::new());
+        self.next_runs.clear();
+        for (timestamp, db, schedule_key) in timetable {
+            self.timetable
+                .insert((timestamp, swap(db), schedule_key.clone()));
+            self.next_runs.insert((swap(db), schedule_key), timestamp);
+        }
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+///
+/// Sets when the schedule's due tasks execute next (None if they don't).
+/// The schedule is the key in the context's selected database
+///
+pub fn set_next_run(ctx: &Context, schedule_key: &str, timestamp: Option<u64>, now: u64) {
+    let db = ctx.get_selected_db();
+    TIMERS.with(|timers| {
+        timers
+            .borrow_mut()
+            .set_next_run(db, schedule_key, timestamp)
+    });
+    arm_timer(ctx, now);
+}
+
+///
+/// Whether the schedule at the key has a next run on the timer
+///
+pub fn has_next_run(ctx: &Context, schedule_key: &str) -> bool {
+    let db = ctx.get_selected_db();
+    TIMERS.with(|timers| timers.borrow().has_next_run(db, schedule_key))
+}
+
+///
+/// Sets the budget of each tick of the timer
+///
+pub fn set_budget(budget: Budget) {
+    BUDGET.with(|cell| cell.set(budget));
+}
+
+pub fn budget() -> Budget {
+    BUDGET.with(|cell| cell.get())
+}
+
+///
+/// Subscribes to SWAPDB so the schedules keep running in the database they moved to,
+/// and to FLUSHDB/FLUSHALL so the flushed schedules stop and release their blocked clients
+///
+pub fn subscribe_to_server_events(ctx: &Context) {
+    ctx.subscribe_to_server_event(raw::REDISMODULE_EVENT_SWAPDB as u64, Some(swap_dbs));
+    ctx.subscribe_to_server_event(raw::REDISMODULE_EVENT_FLUSHDB as u64, Some(flush_db));
+}
+
+unsafe extern "C" fn swap_dbs(
+    ctx: *mut raw::RedisModuleCtx,
+    _eid: raw::RedisModuleEvent,
+    _subevent: u64,
+    data: *mut c_void,
+) {
+    let info = &*(data as *const raw::RedisModuleSwapDbInfo);
+    TIMERS.with(|timers| {
+        timers
+            .borrow_mut()
+            .swap_dbs(info.dbnum_first, info.dbnum_second)
+    });
+    blocked_clients::dbs_swapped(&Context::new(ctx), info.dbnum_first, info.dbnum_second);
+}
+
+unsafe extern "C" fn flush_db(
+    ctx: *mut raw::RedisModuleCtx,
+    _eid: raw::RedisModuleEvent,
+    subevent: u64,
+    data: *mut c_void,
+) {
+    if subevent != raw::REDISMODULE_SUBEVENT_FLUSHDB_END as u64 {
+        return;
+    }
+
+    let info = &*(data as *const raw::RedisModuleFlushInfo);
+    // FLUSHALL flushes the database -1
+    let db = Some(info.dbnum).filter(|db| *db >= 0);
+    TIMERS.with(|timers| timers.borrow_mut().flush(db));
+    let ctx = Context::new(ctx);
+    arm_timer(&ctx, now_millis());
+    blocked_clients::db_flushed(&ctx, db);
+}
+
+// When the Redis timer fires for a run at `next_timestamp`
+fn fires_at(next_timestamp: u64, now: u64) -> u64 {
+    // Execute ASAP if the schedule's time is in the past
+    next_timestamp.clamp(now, now.saturating_add(MAX_TIMER_PERIOD))
+}
+
+// Makes sure the Redis timer fires in time for the first schedule.
+// A timer that fires too early finds nothing due and is armed again
+fn arm_timer(ctx: &Context, now: u64) {
+    TIMERS.with(|timers| {
+        let mut timers = timers.borrow_mut();
+        let next_timestamp = timers.next_timestamp();
+        match (timers.timer, next_timestamp) {
+            (None, None) => return,
+            (Some((_, fires_at)), Some(next_timestamp)) if fires_at <= next_timestamp => return,
+            _ => {}
+        }
+
+        if let Some((timer_id, _)) = timers.timer.take() {
+            ctx.stop_raw_timer(timer_id);
+        }
+        if let Some(next_timestamp) = next_timestamp {
+            let fires_at = fires_at(next_timestamp, now);
+            let timer_id =
+                ctx.create_raw_timer(Duration::from_millis(fires_at - now), Some(fire_timer));
+            timers.timer = Some((timer_id, fires_at));
+        }
+    });
+}
+
+// Executes the due schedules in order until the drain's budget is spent,
+// and returns the ones left
+fn drain_due<F>(due: Vec<NextRun>, drain: &mut Drain, mut exec_due_tasks: F) -> Vec<NextRun>
+where
+    F: FnMut(i32, String, &mut Drain),
+{
+    let mut due = due.into_iter();
+    for (_, db, schedule_key) in due.by_ref() {
+        exec_due_tasks(db, schedule_key, drain);
+        if drain.exhausted() {
+            break;
+        }
+    }
+    due.collect()
+}
+
+extern "C" fn fire_timer(ctx: *mut raw::RedisModuleCtx, _data: *mut c_void) {
+    let ctx = Context::new(ctx);
+    let due = TIMERS.with(|timers| {
+        let mut timers = timers.borrow_mut();
+        timers.timer = None;
+        timers.pop_due(now_millis())
+    });
+    // Executing the due tasks sets the schedules' next runs
+    let mut drain = Drain::new(budget());
+    let left = drain_due(due, &mut drain, |db, schedule_key, drain| {
+        if ctx.select_db(db) {
+            crate::exec_due_tasks(&ctx, schedule_key, drain);
+        }
+    });
+    // They're still due, the timer comes back right away for them
+    TIMERS.with(|timers| timers.borrow_mut().requeue(left));
+    arm_timer(&ctx, now_millis());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fires_at() {
+        assert_eq!(super::fires_at(1_500, 1_000), 1_500);
+        assert_eq!(super::fires_at(500, 1_000), 1_000);
+        assert_eq!(super::fires_at(u64::MAX, 1_000), 1_000 + MAX_TIMER_PERIOD);
+    }
+
+    #[test]
+    fn set_next_run() {
+        let mut timers = Timers::new();
+        timers.set_next_run(0, "schedule-a", Some(20));
+        timers.set_next_run(0, "schedule-b", Some(10));
+        assert_eq!(timers.next_timestamp(), Some(10));
+
+        // A schedule has a single next run
+        timers.set_next_run(0, "schedule-b", Some(30));
+        assert_eq!(timers.next_timestamp(), Some(20));
+        assert_eq!(timers.timetable.len(), 2);
+
+        timers.set_next_run(0, "schedule-a", None);
+        assert_eq!(timers.next_timestamp(), Some(30));
+        assert_eq!(timers.timetable.len(), 1);
+        assert!(!timers.has_next_run(0, "schedule-a"));
+        assert!(timers.has_next_run(0, "schedule-b"));
+        assert!(!timers.has_next_run(1, "schedule-b"));
+    }
+
+    #[test]
+    fn pop_due() {
+        let mut timers = Timers::new();
+        timers.set_next_run(0, "schedule-a", Some(20));
+        timers.set_next_run(0, "schedule-b", Some(10));
+        timers.set_next_run(0, "schedule-c", Some(30));
+
+        assert_eq!(
+            timers.pop_due(20),
+            vec![
+                (10, 0, "schedule-b".to_string()),
+                (20, 0, "schedule-a".to_string())
+            ]
+        );
+        assert_eq!(timers.pop_due(20), Vec::<NextRun>::new());
+        assert_eq!(timers.next_timestamp(), Some(30));
+        assert!(!timers
+            .next_runs
+            .contains_key(&(0, "schedule-a".to_string())));
+    }
+
+    #[test]
+    fn drain_due_stops_at_the_budget() {
+        let mut timers = Timers::new();
+        for i in 0..10 {
+            timers.set_next_run(0, &format!("schedule-{}", i), Some(i));
+        }
+
+        // Each schedule executes one task, the tick has room for four
+        let mut drain = Drain::new(Budget {
+            max_tasks: 4,
+            max_micros: 0,
+        });
+        let mut executed = Vec::new();
+        let left = drain_due(timers.pop_due(100), &mut drain, |_, schedule_key, drain| {
+            drain.spend();
+            executed.push(schedule_key);
+        });
+        assert_eq!(
+            executed,
+            vec!["schedule-0", "schedule-1", "schedule-2", "schedule-3"]
+        );
+        assert_eq!(left.len(), 6);
+
+        // The rest is due on the next tick, at its original timestamp
+        timers.set_next_run(0, "schedule-9", Some(50));
+        timers.requeue(left);
+        assert_eq!(timers.next_timestamp(), Some(4));
+        let due = timers.pop_due(100);
+        assert_eq!(due.len(), 6);
+        assert_eq!(due[5], (9, 0, "schedule-9".to_string()));
+    }
+
+    #[test]
+    fn databases() {
+        let mut timers = Timers::new();
+        timers.set_next_run(0, "schedule", Some(20));
+        timers.set_next_run(3, "schedule", Some(10));
+        assert_eq!(timers.timetable.len(), 2);
+
+        timers.set_next_run(3, "schedule", None);
+        assert_eq!(timers.next_timestamp(), Some(20));
+        assert_eq!(timers.pop_due(20), vec![(20, 0, "schedule".to_string())]);
+    }
+
+    #[test]
+    fn flush() {
+        let mut timers = Timers::new();
+        timers.set_next_run(3, "schedule-a", Some(10));
+        timers.set_next_run(4, "schedule-b", Some(20));
+        timers.set_next_run(5, "schedule-c", Some(30));
+
+        timers.flush(Some(3));
+        assert_eq!(timers.next_timestamp(), Some(20));
+        assert_eq!(timers.timetable.len(), 2);
+
+        timers.flush(None);
+        assert_eq!(timers.next_timestamp(), None);
+        assert!(timers.next_runs.is_empty());
+    }
+
+    #[test]
+    fn swap_dbs() {
+        let mut timers = Timers::new();
+        timers.set_next_run(3, "schedule-a", Some(10));
+        timers.set_next_run(4, "schedule-b", Some(20));
+        timers.set_next_run(5, "schedule-c", Some(30));
+
+        timers.swap_dbs(3, 4);
+        assert_eq!(
+            timers.pop_due(30),
+            vec![
+                (10, 4, "schedule-a".to_string()),
+                (20, 3, "schedule-b".to_string()),
+                (30, 5, "schedule-c".to_string())
+            ]
+        );
+
+        // The swapped schedules are updated in their new database
+        timers.set_next_run(3, "schedule-a", Some(10));
+        timers.swap_dbs(4, 3);
+        timers.set_next_run(4, "schedule-a", None);
+        assert_eq!(timers.next_timestamp(), None);
+    }
+}
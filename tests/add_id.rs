@@ -0,0 +1,136 @@
+mod utils;
+use utils::open_redis_connection;
+
+const PREFIX: &str = "{test-add-id}:";
+
+fn k(val: &str) -> String {
+    PREFIX.to_string() + val
+}
+
+fn cleanup() {
+    let mut con = open_redis_connection();
+    redis::cmd("DEL")
+        .arg(k("schedule"))
+        .arg(k("list"))
+        .execute(&mut con);
+}
+
+fn add(
+    con: &mut dyn redis::ConnectionLike,
+    delay: u64,
+    flags: &[&str],
+    item: &str,
+) -> redis::RedisResult<Option<String>> {
+    redis::cmd("SCHEDULE.ADD")
+        .arg(k("schedule"))
+        .arg(delay)
+        .arg("ID")
+        .arg("task-a")
+        .arg(flags.to_vec())
+        .arg("rpush")
+        .arg(k("list"))
+        .arg(item)
+        .query(con)
+}
+
+fn scan(
+    con: &mut dyn redis::ConnectionLike,
+) -> redis::RedisResult<Vec<(u64, String, Vec<String>)>> {
+    redis::cmd("SCHEDULE.SCAN").arg(k("schedule")).query(con)
+}
+
+fn item_of(schedule: &[(u64, String, Vec<String>)]) -> String {
+    schedule[0].2[2].clone()
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_add_with_id() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+
+    assert_eq!(
+        add(&mut con, 100, &[], "item-1")?,
+        Some("task-a".to_string())
+    );
+    assert_eq!(
+        add(&mut con, 200, &[], "item-2")?,
+        Some("task-a".to_string())
+    );
+
+    // The task was replaced, not duplicated
+    let schedule = scan(&mut con)?;
+    assert_eq!(schedule.len(), 1);
+    assert_eq!(item_of(&schedule), "item-2");
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_add_nx_xx() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+
+    assert_eq!(add(&mut con, 100, &["XX"], "item-1")?, None);
+    assert!(scan(&mut con)?.is_empty());
+
+    assert_eq!(
+        add(&mut con, 100, &["NX"], "item-1")?,
+        Some("task-a".to_string())
+    );
+    assert_eq!(add(&mut con, 100, &["NX"], "item-2")?, None);
+    assert_eq!(item_of(&scan(&mut con)?), "item-1");
+
+    assert_eq!(
+        add(&mut con, 100, &["XX"], "item-3")?,
+        Some("task-a".to_string())
+    );
+    assert_eq!(item_of(&scan(&mut con)?), "item-3");
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_add_gt_lt() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+
+    add(&mut con, 100, &[], "item-1")?;
+    assert_eq!(add(&mut con, 50, &["GT"], "item-2")?, None);
+    assert_eq!(
+        add(&mut con, 200, &["GT"], "item-3")?,
+        Some("task-a".to_string())
+    );
+    assert_eq!(add(&mut con, 300, &["LT"], "item-4")?, None);
+    assert_eq!(
+        add(&mut con, 150, &["LT"], "item-5")?,
+        Some("task-a".to_string())
+    );
+
+    let schedule = scan(&mut con)?;
+    assert_eq!(schedule.len(), 1);
+    assert_eq!(item_of(&schedule), "item-5");
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_add_flags_validation() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+
+    assert!(add(&mut con, 100, &["NX", "XX"], "item-1").is_err());
+    assert!(add(&mut con, 100, &["NX", "GT"], "item-1").is_err());
+    assert!(add(&mut con, 100, &["GT", "LT"], "item-1").is_err());
+
+    let result: redis::RedisResult<String> = redis::cmd("SCHEDULE.ADD")
+        .arg(k("schedule"))
+        .arg(100)
+        .arg("NX")
+        .arg("rpush")
+        .arg(k("list"))
+        .arg("item-1")
+        .query(&mut con);
+    assert!(result.is_err());
+    Ok(())
+}
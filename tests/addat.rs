@@ -0,0 +1,84 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+mod utils;
+use utils::open_redis_connection;
+
+const PREFIX: &str = "{test-addat}:";
+
+fn k(val: &str) -> String {
+    PREFIX.to_string() + val
+}
+
+fn cleanup() {
+    let mut con = open_redis_connection();
+    redis::cmd("DEL")
+        .arg(k("schedule"))
+        .arg(k("list"))
+        .execute(&mut con);
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_addat_and_setat() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+
+    let timestamp = now() + 100;
+    let task_id: String = redis::cmd("SCHEDULE.ADDAT")
+        .arg(k("schedule"))
+        .arg(timestamp)
+        .arg("rpush")
+        .arg(k("list"))
+        .arg("item-1")
+        .query(&mut con)?;
+
+    let schedule: Vec<(u64, String, Vec<String>)> = redis::cmd("SCHEDULE.SCAN")
+        .arg(k("schedule"))
+        .query(&mut con)?;
+    assert_eq!(schedule[0].0, timestamp * 1000);
+
+    // Move it to the past, so it executes right away
+    let new_timestamp: u64 = redis::cmd("SCHEDULE.SETAT")
+        .arg(k("schedule"))
+        .arg(&task_id)
+        .arg(now() - 10)
+        .query(&mut con)?;
+    assert!(new_timestamp < timestamp * 1000);
+
+    std::thread::sleep(Duration::from_secs(2));
+    let list_size: usize = redis::cmd("LLEN").arg(k("list")).query(&mut con)?;
+    assert_eq!(list_size, 1);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_add_with_milliseconds() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+
+    let _: String = redis::cmd("SCHEDULE.ADD")
+        .arg(k("schedule"))
+        .arg("PX")
+        .arg(250)
+        .arg("rpush")
+        .arg(k("list"))
+        .arg("item-1")
+        .query(&mut con)?;
+
+    std::thread::sleep(Duration::from_millis(100));
+    let list_size: usize = redis::cmd("LLEN").arg(k("list")).query(&mut con)?;
+    assert_eq!(list_size, 0);
+
+    std::thread::sleep(Duration::from_millis(400));
+    let list_size: usize = redis::cmd("LLEN").arg(k("list")).query(&mut con)?;
+    assert_eq!(list_size, 1);
+    Ok(())
+}
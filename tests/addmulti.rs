@@ -0,0 +1,205 @@
+mod utils;
+use utils::open_redis_connection;
+
+const PREFIX: &str = "{test-addmulti}:";
+
+fn k(val: &str) -> String {
+    PREFIX.to_string() + val
+}
+
+fn cleanup() {
+    let mut con = open_redis_connection();
+    redis::cmd("DEL")
+        .arg(k("schedule"))
+        .arg(k("pending"))
+        .arg(k("ready"))
+        .arg(k("count"))
+        .arg(k("string"))
+        .execute(&mut con);
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_addmulti_wrong_number_of_commands() -> redis::RedisResult<()> {
+    let mut con = open_redis_connection();
+    let result: redis::RedisResult<String> = redis::cmd("SCHEDULE.ADDMULTI")
+        .arg(k("schedule"))
+        .arg(0)
+        .arg(3)
+        .arg("LPUSH")
+        .arg(k("ready"))
+        .arg("item-1")
+        .arg(";")
+        .arg("INCR")
+        .arg(k("count"))
+        .query(&mut con);
+    assert!(result.is_err());
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_addmulti_cross_slot() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+    let result: redis::RedisResult<String> = redis::cmd("SCHEDULE.ADDMULTI")
+        .arg(k("schedule"))
+        .arg(60)
+        .arg(2)
+        .arg("LPUSH")
+        .arg(k("ready"))
+        .arg("item-1")
+        .arg(";")
+        .arg("INCR")
+        .arg("{another-slot}:count")
+        .query(&mut con);
+    // Only a cluster checks the slots, elsewhere the task is added
+    if cfg!(feature = "test_cluster") {
+        assert!(result.unwrap_err().to_string().contains("CROSSSLOT"));
+    } else {
+        let task_id = result?;
+        let task: Option<Vec<redis::Value>> = redis::cmd("SCHEDULE.GET")
+            .arg(k("schedule"))
+            .arg(&task_id)
+            .query(&mut con)?;
+        assert!(task.is_some());
+    }
+    cleanup();
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_addmulti_getkeys() -> redis::RedisResult<()> {
+    let mut con = open_redis_connection();
+    let keys: Vec<String> = redis::cmd("COMMAND")
+        .arg("GETKEYS")
+        .arg("SCHEDULE.ADDMULTI")
+        .arg(k("schedule"))
+        .arg("PX")
+        .arg(100)
+        .arg("IF")
+        .arg("EXISTS")
+        .arg(k("guard"))
+        .arg("ONSUCCESS")
+        .arg(2)
+        .arg("INCR")
+        .arg(k("success"))
+        .arg(2)
+        .arg("LPUSH")
+        .arg(k("ready"))
+        .arg("item-1")
+        .arg(";")
+        .arg("INCR")
+        .arg(k("count"))
+        .query(&mut con)?;
+    assert_eq!(
+        keys,
+        vec![
+            k("schedule"),
+            k("guard"),
+            k("success"),
+            k("ready"),
+            k("count")
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_addmulti_exec() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+    let _: () = redis::cmd("RPUSH")
+        .arg(k("pending"))
+        .arg("item-1")
+        .query(&mut con)?;
+
+    let task_id: String = redis::cmd("SCHEDULE.ADDMULTI")
+        .arg(k("schedule"))
+        .arg(100)
+        .arg(3)
+        .arg("LREM")
+        .arg(k("pending"))
+        .arg(0)
+        .arg("item-1")
+        .arg(";")
+        .arg("LPUSH")
+        .arg(k("ready"))
+        .arg("item-1")
+        .arg(";")
+        .arg("INCR")
+        .arg(k("count"))
+        .query(&mut con)?;
+
+    let _: () = redis::cmd("SCHEDULE.EXEC")
+        .arg(k("schedule"))
+        .arg(&task_id)
+        .query(&mut con)?;
+
+    let pending: Vec<String> = redis::cmd("LRANGE")
+        .arg(k("pending"))
+        .arg(0)
+        .arg(-1)
+        .query(&mut con)?;
+    assert!(pending.is_empty());
+    let ready: Vec<String> = redis::cmd("LRANGE")
+        .arg(k("ready"))
+        .arg(0)
+        .arg(-1)
+        .query(&mut con)?;
+    assert_eq!(ready, vec!["item-1".to_string()]);
+    let count: u64 = redis::cmd("GET").arg(k("count")).query(&mut con)?;
+    assert_eq!(count, 1);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+// The 2nd command hits WRONGTYPE, the 1st one is rolled back and the 3rd one doesn't run
+fn test_addmulti_all_or_nothing() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+    let _: () = redis::cmd("RPUSH")
+        .arg(k("pending"))
+        .arg("item-1")
+        .query(&mut con)?;
+    let _: () = redis::cmd("SET")
+        .arg(k("string"))
+        .arg("value")
+        .query(&mut con)?;
+
+    let task_id: String = redis::cmd("SCHEDULE.ADDMULTI")
+        .arg(k("schedule"))
+        .arg(100)
+        .arg(3)
+        .arg("LREM")
+        .arg(k("pending"))
+        .arg(0)
+        .arg("item-1")
+        .arg(";")
+        .arg("LPUSH")
+        .arg(k("string"))
+        .arg("item-1")
+        .arg(";")
+        .arg("INCR")
+        .arg(k("count"))
+        .query(&mut con)?;
+
+    let result: redis::RedisResult<()> = redis::cmd("SCHEDULE.EXEC")
+        .arg(k("schedule"))
+        .arg(&task_id)
+        .query(&mut con);
+    assert!(result.unwrap_err().to_string().contains("WRONGTYPE"));
+
+    let pending: Vec<String> = redis::cmd("LRANGE")
+        .arg(k("pending"))
+        .arg(0)
+        .arg(-1)
+        .query(&mut con)?;
+    assert_eq!(pending, vec!["item-1".to_string()]);
+    let count: Option<u64> = redis::cmd("GET").arg(k("count")).query(&mut con)?;
+    assert_eq!(count, None);
+    Ok(())
+}
@@ -0,0 +1,151 @@
+mod utils;
+use utils::open_redis_connection;
+
+const PREFIX: &str = "{test-after}:";
+
+fn k(val: &str) -> String {
+    PREFIX.to_string() + val
+}
+
+fn cleanup() {
+    let mut con = open_redis_connection();
+    redis::cmd("DEL")
+        .arg(k("schedule"))
+        .arg(k("list"))
+        .arg(k("string"))
+        .execute(&mut con);
+}
+
+fn add_task(
+    con: &mut dyn redis::ConnectionLike,
+    after: Option<&str>,
+    list: &str,
+    item: &str,
+) -> redis::RedisResult<String> {
+    let mut cmd = redis::cmd("SCHEDULE.ADD");
+    cmd.arg(k("schedule")).arg(0);
+    if let Some(after) = after {
+        cmd.arg("AFTER").arg(after);
+    }
+    cmd.arg("rpush").arg(k(list)).arg(item).query(con)
+}
+
+fn scan_ids(con: &mut dyn redis::ConnectionLike) -> redis::RedisResult<Vec<String>> {
+    let schedule: Vec<(u64, String, Vec<String>)> =
+        redis::cmd("SCHEDULE.SCAN").arg(k("schedule")).query(con)?;
+    Ok(schedule
+        .into_iter()
+        .map(|(_, task_id, _)| task_id)
+        .collect())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_after_unknown_task() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+    assert!(add_task(&mut con, Some("unknown"), "list", "item-1").is_err());
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_after_waits_for_parent() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+
+    let parent_id = add_task(&mut con, None, "list", "item-1")?;
+    let child_id = add_task(&mut con, Some(&parent_id), "list", "item-2")?;
+
+    // The child can't run before its parent
+    let result: redis::RedisResult<()> = redis::cmd("SCHEDULE.EXEC")
+        .arg(k("schedule"))
+        .arg(&child_id)
+        .query(&mut con);
+    assert!(result.is_err());
+
+    let _: () = redis::cmd("SCHEDULE.EXEC")
+        .arg(k("schedule"))
+        .arg(&parent_id)
+        .query(&mut con)?;
+    let _: () = redis::cmd("SCHEDULE.EXEC")
+        .arg(k("schedule"))
+        .arg(&child_id)
+        .query(&mut con)?;
+
+    let list: Vec<String> = redis::cmd("LRANGE")
+        .arg(k("list"))
+        .arg(0)
+        .arg(-1)
+        .query(&mut con)?;
+    assert_eq!(list, vec!["item-1".to_string(), "item-2".to_string()]);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_after_failed_parent() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+    let _: () = redis::cmd("SET")
+        .arg(k("string"))
+        .arg("value")
+        .query(&mut con)?;
+
+    let parent_id = add_task(&mut con, None, "string", "item-1")?;
+    let child_id = add_task(&mut con, Some(&parent_id), "list", "item-2")?;
+    let grandchild_id = add_task(&mut con, Some(&child_id), "list", "item-3")?;
+    assert_eq!(scan_ids(&mut con)?.len(), 3);
+
+    let result: redis::RedisResult<()> = redis::cmd("SCHEDULE.EXEC")
+        .arg(k("schedule"))
+        .arg(&parent_id)
+        .query(&mut con);
+    assert!(result.is_err());
+
+    // The tasks waiting for it are cancelled
+    let ids = scan_ids(&mut con)?;
+    assert!(!ids.contains(&child_id));
+    assert!(!ids.contains(&grandchild_id));
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_after_cycle() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+
+    let task_a = add_task(&mut con, None, "list", "item-1")?;
+    let task_b = add_task(&mut con, Some(&task_a), "list", "item-2")?;
+    let _: String = redis::cmd("SCHEDULE.ADD")
+        .arg(k("schedule"))
+        .arg(0)
+        .arg("ID")
+        .arg("task-c")
+        .arg("AFTER")
+        .arg(&task_b)
+        .arg("rpush")
+        .arg(k("list"))
+        .arg("item-3")
+        .query(&mut con)?;
+
+    // Re-adding the first task after the last one would make them all wait forever
+    let result: redis::RedisResult<String> = redis::cmd("SCHEDULE.ADD")
+        .arg(k("schedule"))
+        .arg(0)
+        .arg("ID")
+        .arg(&task_a)
+        .arg("AFTER")
+        .arg("task-c")
+        .arg("rpush")
+        .arg(k("list"))
+        .arg("item-1")
+        .query(&mut con);
+    assert!(result
+        .unwrap_err()
+        .to_string()
+        .contains("wait for each other"));
+    assert_eq!(scan_ids(&mut con)?.len(), 3);
+    Ok(())
+}
@@ -0,0 +1,114 @@
+mod utils;
+use utils::open_redis_connection;
+
+const PREFIX: &str = "{test-bpopdue}:";
+
+fn k(val: &str) -> String {
+    PREFIX.to_string() + val
+}
+
+fn cleanup() {
+    let mut con = open_redis_connection();
+    redis::cmd("DEL").arg(k("schedule")).execute(&mut con);
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_bpopdue_timeout() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+
+    let popped: Option<Vec<(String, u64, String)>> = redis::cmd("SCHEDULE.BPOPDUE")
+        .arg(k("schedule"))
+        .arg("PX")
+        .arg(100)
+        .query(&mut con)?;
+    assert_eq!(popped, None);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+// Blocks a client until a payload task added by another client is due
+fn test_bpopdue_wakes_up() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+
+    let consumer = std::thread::spawn(|| {
+        let mut con = open_redis_connection();
+        let popped: redis::RedisResult<Option<Vec<(String, u64, String)>>> =
+            redis::cmd("SCHEDULE.BPOPDUE")
+                .arg(k("schedule"))
+                .arg(5)
+                .query(&mut con);
+        popped
+    });
+
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    let task_id: String = redis::cmd("SCHEDULE.ADD")
+        .arg(k("schedule"))
+        .arg("PX")
+        .arg(200)
+        .arg("PAYLOAD")
+        .arg("payload-1")
+        .query(&mut con)?;
+
+    let popped = consumer.join().unwrap()?.unwrap();
+    assert_eq!(popped.len(), 1);
+    assert_eq!(popped[0].0, task_id);
+    assert_eq!(popped[0].2, "payload-1");
+
+    let schedule: Vec<(u64, String, Vec<String>)> = redis::cmd("SCHEDULE.SCAN")
+        .arg(k("schedule"))
+        .query(&mut con)?;
+    assert!(schedule.is_empty());
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_bpopdue_in_multi() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+
+    let (popped,): (Option<Vec<(String, u64, String)>>,) = redis::pipe()
+        .atomic()
+        .cmd("SCHEDULE.BPOPDUE")
+        .arg(k("schedule"))
+        .arg(0)
+        .query(&mut con)?;
+    assert_eq!(popped, None);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+// Deleting the schedule releases the client right away
+fn test_bpopdue_schedule_deleted() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+    let _: String = redis::cmd("SCHEDULE.ADD")
+        .arg(k("schedule"))
+        .arg(60)
+        .arg("PAYLOAD")
+        .arg("payload-1")
+        .query(&mut con)?;
+
+    let started = std::time::Instant::now();
+    let consumer = std::thread::spawn(|| {
+        let mut con = open_redis_connection();
+        let popped: redis::RedisResult<Option<Vec<(String, u64, String)>>> =
+            redis::cmd("SCHEDULE.BPOPDUE")
+                .arg(k("schedule"))
+                .arg(5)
+                .query(&mut con);
+        popped
+    });
+
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    let _: () = redis::cmd("DEL").arg(k("schedule")).query(&mut con)?;
+
+    assert_eq!(consumer.join().unwrap()?, None);
+    assert!(started.elapsed() < std::time::Duration::from_secs(5));
+    Ok(())
+}
@@ -0,0 +1,111 @@
+mod utils;
+use utils::open_redis_connection;
+
+const PREFIX: &str = "{test-callbacks}:";
+
+fn k(val: &str) -> String {
+    PREFIX.to_string() + val
+}
+
+fn cleanup() {
+    let mut con = open_redis_connection();
+    redis::cmd("DEL")
+        .arg(k("schedule"))
+        .arg(k("list"))
+        .arg(k("string"))
+        .arg(k("log"))
+        .execute(&mut con);
+}
+
+fn log(con: &mut dyn redis::ConnectionLike) -> redis::RedisResult<Vec<String>> {
+    redis::cmd("LRANGE").arg(k("log")).arg(0).arg(-1).query(con)
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_on_success() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+
+    let task_id: String = redis::cmd("SCHEDULE.ADD")
+        .arg(k("schedule"))
+        .arg(100)
+        .arg("ONSUCCESS")
+        .arg(4)
+        .arg("rpush")
+        .arg(k("log"))
+        .arg("success")
+        .arg("{reply}")
+        .arg("ONFAILURE")
+        .arg(3)
+        .arg("rpush")
+        .arg(k("log"))
+        .arg("failure")
+        .arg("rpush")
+        .arg(k("list"))
+        .arg("item-1")
+        .query(&mut con)?;
+    let _: () = redis::cmd("SCHEDULE.EXEC")
+        .arg(k("schedule"))
+        .arg(&task_id)
+        .query(&mut con)?;
+
+    assert_eq!(log(&mut con)?, vec!["success".to_string(), "1".to_string()]);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_on_failure() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+    let _: () = redis::cmd("SET")
+        .arg(k("string"))
+        .arg("value")
+        .query(&mut con)?;
+
+    let task_id: String = redis::cmd("SCHEDULE.ADD")
+        .arg(k("schedule"))
+        .arg(100)
+        .arg("ONSUCCESS")
+        .arg(3)
+        .arg("rpush")
+        .arg(k("log"))
+        .arg("success")
+        .arg("ONFAILURE")
+        .arg(3)
+        .arg("rpush")
+        .arg(k("log"))
+        .arg("{error}")
+        .arg("rpush")
+        .arg(k("string"))
+        .arg("item-1")
+        .query(&mut con)?;
+    let result: redis::RedisResult<()> = redis::cmd("SCHEDULE.EXEC")
+        .arg(k("schedule"))
+        .arg(&task_id)
+        .query(&mut con);
+    assert!(result.is_err());
+
+    let log = log(&mut con)?;
+    assert_eq!(log.len(), 1);
+    assert!(log[0].contains("WRONGTYPE"));
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_callback_wrong_number_of_args() -> redis::RedisResult<()> {
+    let mut con = open_redis_connection();
+    let result: redis::RedisResult<String> = redis::cmd("SCHEDULE.ADD")
+        .arg(k("schedule"))
+        .arg(100)
+        .arg("ONSUCCESS")
+        .arg(10)
+        .arg("rpush")
+        .arg(k("log"))
+        .arg("success")
+        .query(&mut con);
+    assert!(result.is_err());
+    Ok(())
+}
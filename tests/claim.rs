@@ -0,0 +1,109 @@
+mod utils;
+use utils::open_redis_connection;
+
+const PREFIX: &str = "{test-claim}:";
+
+fn k(val: &str) -> String {
+    PREFIX.to_string() + val
+}
+
+fn cleanup() {
+    let mut con = open_redis_connection();
+    redis::cmd("DEL").arg(k("schedule")).execute(&mut con);
+}
+
+fn add_payload(con: &mut dyn redis::ConnectionLike, payload: &str) -> redis::RedisResult<String> {
+    redis::cmd("SCHEDULE.ADD")
+        .arg(k("schedule"))
+        .arg(0)
+        .arg("PAYLOAD")
+        .arg(payload)
+        .query(con)
+}
+
+fn claim(
+    con: &mut dyn redis::ConnectionLike,
+    consumer: &str,
+) -> redis::RedisResult<Vec<(String, u64, String, u64)>> {
+    redis::cmd("SCHEDULE.CLAIM")
+        .arg(k("schedule"))
+        .arg(consumer)
+        .arg("LEASE")
+        .arg("PX")
+        .arg(200)
+        .arg("COUNT")
+        .arg(10)
+        .query(con)
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_claim_and_ack() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+    let task_id = add_payload(&mut con, "payload-1")?;
+
+    let claimed = claim(&mut con, "worker-1")?;
+    assert_eq!(claimed.len(), 1);
+    assert_eq!(claimed[0].0, task_id);
+    assert_eq!(claimed[0].2, "payload-1");
+    assert_eq!(claimed[0].3, 1);
+
+    // Leased tasks stay in the schedule, but can't be claimed again
+    assert!(claim(&mut con, "worker-2")?.is_empty());
+    let schedule: Vec<(u64, String, Vec<String>)> = redis::cmd("SCHEDULE.SCAN")
+        .arg(k("schedule"))
+        .query(&mut con)?;
+    assert_eq!(schedule.len(), 1);
+
+    let acked: i64 = redis::cmd("SCHEDULE.ACK")
+        .arg(k("schedule"))
+        .arg(&task_id)
+        .query(&mut con)?;
+    assert_eq!(acked, 1);
+    let acked: i64 = redis::cmd("SCHEDULE.ACK")
+        .arg(k("schedule"))
+        .arg(&task_id)
+        .query(&mut con)?;
+    assert_eq!(acked, 0);
+
+    let schedule: Vec<(u64, String, Vec<String>)> = redis::cmd("SCHEDULE.SCAN")
+        .arg(k("schedule"))
+        .query(&mut con)?;
+    assert!(schedule.is_empty());
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_nack_and_lease_expiration() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+    let task_id = add_payload(&mut con, "payload-1")?;
+
+    claim(&mut con, "worker-1")?;
+    let released: i64 = redis::cmd("SCHEDULE.NACK")
+        .arg(k("schedule"))
+        .arg(&task_id)
+        .query(&mut con)?;
+    assert_eq!(released, 1);
+
+    // Released early
+    let claimed = claim(&mut con, "worker-2")?;
+    assert_eq!(claimed.len(), 1);
+    assert_eq!(claimed[0].3, 2);
+
+    // The lease expires without an ACK
+    std::thread::sleep(std::time::Duration::from_millis(400));
+    let task: Vec<redis::Value> = redis::cmd("SCHEDULE.GET")
+        .arg(k("schedule"))
+        .arg(&task_id)
+        .query(&mut con)?;
+    assert_eq!(task[13], redis::Value::Nil);
+
+    let claimed = claim(&mut con, "worker-3")?;
+    assert_eq!(claimed.len(), 1);
+    assert_eq!(claimed[0].0, task_id);
+    assert_eq!(claimed[0].3, 3);
+    Ok(())
+}
@@ -0,0 +1,98 @@
+mod utils;
+use utils::open_redis_connection;
+
+const PREFIX: &str = "{test-cron}:";
+
+fn k(val: &str) -> String {
+    PREFIX.to_string() + val
+}
+
+fn cleanup() {
+    let mut con = open_redis_connection();
+    redis::cmd("DEL")
+        .arg(k("schedule"))
+        .arg(k("list"))
+        .execute(&mut con);
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_cron_invalid_expression() -> redis::RedisResult<()> {
+    let mut con = open_redis_connection();
+    let result: redis::RedisResult<String> = redis::cmd("SCHEDULE.CRON")
+        .arg(k("schedule"))
+        .arg("*/5 * *")
+        .arg("rpush")
+        .arg(k("list"))
+        .arg("item-1")
+        .query(&mut con);
+
+    assert!(result.is_err());
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+// Executes a cron task by hand and checks that it goes back
+// into the schedule at its next occurrence
+fn test_cron_task_is_rescheduled() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+
+    let task_id: String = redis::cmd("SCHEDULE.CRON")
+        .arg(k("schedule"))
+        .arg("* * * * *")
+        .arg("rpush")
+        .arg(k("list"))
+        .arg("item-1")
+        .query(&mut con)?;
+
+    let schedule: Vec<(u64, String, Vec<String>)> = redis::cmd("SCHEDULE.SCAN")
+        .arg(k("schedule"))
+        .query(&mut con)?;
+    assert_eq!(schedule.len(), 1);
+    let (first_run, scanned_id, _) = &schedule[0];
+    assert_eq!(scanned_id, &task_id);
+    assert_eq!(first_run % 60_000, 0);
+
+    let _: () = redis::cmd("SCHEDULE.EXEC")
+        .arg(k("schedule"))
+        .arg(&task_id)
+        .query(&mut con)?;
+
+    let list: Vec<String> = redis::cmd("LRANGE")
+        .arg(k("list"))
+        .arg(0)
+        .arg(-1)
+        .query(&mut con)?;
+    assert_eq!(list, vec!["item-1".to_string()]);
+
+    let schedule: Vec<(u64, String, Vec<String>)> = redis::cmd("SCHEDULE.SCAN")
+        .arg(k("schedule"))
+        .query(&mut con)?;
+    assert_eq!(schedule.len(), 1);
+    let (second_run, scanned_id, _) = &schedule[0];
+    assert_eq!(scanned_id, &task_id);
+    assert_eq!(*second_run, first_run + 60_000);
+
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+// Only SCHEDULE.CRON computes the first run from the expression
+fn test_cron_option_is_rejected() -> redis::RedisResult<()> {
+    let mut con = open_redis_connection();
+    let result: redis::RedisResult<String> = redis::cmd("SCHEDULE.ADD")
+        .arg(k("schedule"))
+        .arg(10)
+        .arg("CRON")
+        .arg("* * * * *")
+        .arg("rpush")
+        .arg(k("list"))
+        .arg("item-1")
+        .query(&mut con);
+
+    assert!(result.is_err());
+    Ok(())
+}
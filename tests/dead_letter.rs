@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+
+mod utils;
+use utils::open_redis_connection;
+
+const PREFIX: &str = "{test-dead-letter}:";
+
+// SCHEDULE.CONFIG's reply
+type Config<DeadLetter, Deliver> = (
+    String,
+    DeadLetter,
+    String,
+    Deliver,
+    String,
+    u64,
+    String,
+    Option<Vec<String>>,
+);
+
+fn k(val: &str) -> String {
+    PREFIX.to_string() + val
+}
+
+fn cleanup() {
+    let mut con = open_redis_connection();
+    redis::cmd("DEL")
+        .arg(k("schedule"))
+        .arg(k("dead-schedule"))
+        .arg(k("dead-stream"))
+        .arg(k("string"))
+        .execute(&mut con);
+    // RPUSH fails on a string
+    redis::cmd("SET")
+        .arg(k("string"))
+        .arg("value")
+        .execute(&mut con);
+}
+
+fn add_failing_task(con: &mut dyn redis::ConnectionLike) -> redis::RedisResult<String> {
+    redis::cmd("SCHEDULE.ADD")
+        .arg(k("schedule"))
+        .arg(100)
+        .arg("rpush")
+        .arg(k("string"))
+        .arg("item-1")
+        .query(con)
+}
+
+fn read_stream(
+    con: &mut dyn redis::ConnectionLike,
+) -> redis::RedisResult<Vec<HashMap<String, String>>> {
+    let entries: Vec<(String, HashMap<String, String>)> = redis::cmd("XRANGE")
+        .arg(k("dead-stream"))
+        .arg("-")
+        .arg("+")
+        .query(con)?;
+    Ok(entries.into_iter().map(|(_, fields)| fields).collect())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_dead_letter_to_stream() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+
+    let _: () = redis::cmd("SCHEDULE.CONFIG")
+        .arg(k("schedule"))
+        .arg("DEADLETTER")
+        .arg("STREAM")
+        .arg(k("dead-stream"))
+        .query(&mut con)?;
+    let task_id = add_failing_task(&mut con)?;
+
+    let result: redis::RedisResult<()> = redis::cmd("SCHEDULE.EXEC")
+        .arg(k("schedule"))
+        .arg(&task_id)
+        .query(&mut con);
+    assert!(result.is_err());
+
+    let entries = read_stream(&mut con)?;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0]["id"], task_id);
+    assert_eq!(entries[0]["schedule"], k("schedule"));
+    assert!(entries[0]["error"].contains("WRONGTYPE"));
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_dead_letter_config() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+
+    let result: redis::RedisResult<()> = redis::cmd("SCHEDULE.CONFIG")
+        .arg(k("schedule"))
+        .arg("DEADLETTER")
+        .arg("SCHEDULE")
+        .arg(k("schedule"))
+        .query(&mut con);
+    assert!(result.is_err());
+
+    let _: () = redis::cmd("SCHEDULE.CONFIG")
+        .arg(k("schedule"))
+        .arg("DEADLETTER")
+        .arg("SCHEDULE")
+        .arg(k("dead-schedule"))
+        .query(&mut con)?;
+    let (_, target, _, _, _, _, _, _): Config<Vec<String>, Option<Vec<String>>> =
+        redis::cmd("SCHEDULE.CONFIG")
+            .arg(k("schedule"))
+            .query(&mut con)?;
+    assert_eq!(target, vec!["schedule".to_string(), k("dead-schedule")]);
+
+    let _: () = redis::cmd("SCHEDULE.CONFIG")
+        .arg(k("schedule"))
+        .arg("DEADLETTER")
+        .arg("NONE")
+        .query(&mut con)?;
+    let (_, target, _, _, _, _, _, _): Config<Option<Vec<String>>, Option<Vec<String>>> =
+        redis::cmd("SCHEDULE.CONFIG")
+            .arg(k("schedule"))
+            .query(&mut con)?;
+    assert_eq!(target, None);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+// The dead-letter schedule keeps the task without running it, until it's executed by hand
+// (and dead-lettered to the dead-letter schedule's own target)
+fn test_dead_letter_to_schedule() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+
+    redis::pipe()
+        .cmd("SCHEDULE.CONFIG")
+        .arg(k("schedule"))
+        .arg("DEADLETTER")
+        .arg("SCHEDULE")
+        .arg(k("dead-schedule"))
+        .cmd("SCHEDULE.CONFIG")
+        .arg(k("dead-schedule"))
+        .arg("DEADLETTER")
+        .arg("STREAM")
+        .arg(k("dead-stream"))
+        .execute(&mut con);
+    let task_id = add_failing_task(&mut con)?;
+
+    let result: redis::RedisResult<()> = redis::cmd("SCHEDULE.EXEC")
+        .arg(k("schedule"))
+        .arg(&task_id)
+        .query(&mut con);
+    assert!(result.is_err());
+
+    let schedule: Vec<(u64, String, Vec<String>)> = redis::cmd("SCHEDULE.SCAN")
+        .arg(k("dead-schedule"))
+        .query(&mut con)?;
+    assert_eq!(schedule.len(), 1);
+    assert_eq!(schedule[0].1, task_id);
+    let (_, _, _, backlog, _, next_run): (String, u64, String, u64, String, Option<u64>) =
+        redis::cmd("SCHEDULE.STATS")
+            .arg(k("dead-schedule"))
+            .query(&mut con)?;
+    assert_eq!((backlog, next_run), (0, None));
+
+    let result: redis::RedisResult<()> = redis::cmd("SCHEDULE.EXEC")
+        .arg(k("dead-schedule"))
+        .arg(&task_id)
+        .query(&mut con);
+    assert!(result.is_err());
+    let entries = read_stream(&mut con)?;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0]["id"], task_id);
+    assert_eq!(entries[0]["schedule"], k("dead-schedule"));
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_dead_letter_cross_slot() -> redis::RedisResult<()> {
+    let mut con = open_redis_connection();
+    let result: redis::RedisResult<()> = redis::cmd("SCHEDULE.CONFIG")
+        .arg(k("cross-slot-schedule"))
+        .arg("DEADLETTER")
+        .arg("STREAM")
+        .arg("{another-slot}:dead-stream")
+        .query(&mut con);
+    // Only a cluster checks the slots
+    match result {
+        Err(error) => {
+            let error = error.to_string();
+            assert!(error.contains("CROSSSLOT") || error.contains("same slot"));
+        }
+        Ok(_) => redis::cmd("DEL")
+            .arg(k("cross-slot-schedule"))
+            .execute(&mut con),
+    }
+    Ok(())
+}
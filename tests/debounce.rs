@@ -0,0 +1,78 @@
+mod utils;
+use utils::open_redis_connection;
+
+const PREFIX: &str = "{test-debounce}:";
+
+fn k(val: &str) -> String {
+    PREFIX.to_string() + val
+}
+
+fn cleanup() {
+    let mut con = open_redis_connection();
+    redis::cmd("DEL")
+        .arg(k("schedule"))
+        .arg(k("list"))
+        .execute(&mut con);
+}
+
+fn debounce(
+    con: &mut dyn redis::ConnectionLike,
+    delay: u64,
+    item: &str,
+) -> redis::RedisResult<String> {
+    redis::cmd("SCHEDULE.DEBOUNCE")
+        .arg(k("schedule"))
+        .arg("recompute")
+        .arg(delay)
+        .arg("rpush")
+        .arg(k("list"))
+        .arg(item)
+        .query(con)
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_debounce_rearms_the_task() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+
+    assert_eq!(debounce(&mut con, 100, "item-1")?, "recompute");
+    let first: Vec<(u64, String, Vec<String>)> = redis::cmd("SCHEDULE.SCAN")
+        .arg(k("schedule"))
+        .query(&mut con)?;
+
+    assert_eq!(debounce(&mut con, 200, "item-2")?, "recompute");
+    let second: Vec<(u64, String, Vec<String>)> = redis::cmd("SCHEDULE.SCAN")
+        .arg(k("schedule"))
+        .query(&mut con)?;
+
+    // Still a single task, pushed back, with the last command
+    assert_eq!(second.len(), 1);
+    assert!(second[0].0 > first[0].0);
+    assert_eq!(second[0].1, "recompute");
+    assert_eq!(
+        second[0].2,
+        vec!["rpush".to_string(), k("list"), "item-2".to_string()]
+    );
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_debounce_runs_once() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+
+    for item in &["item-1", "item-2", "item-3"] {
+        debounce(&mut con, 1, item)?;
+    }
+    std::thread::sleep(std::time::Duration::from_millis(1500));
+
+    let list: Vec<String> = redis::cmd("LRANGE")
+        .arg(k("list"))
+        .arg(0)
+        .arg(-1)
+        .query(&mut con)?;
+    assert_eq!(list, vec!["item-3".to_string()]);
+    Ok(())
+}
@@ -0,0 +1,123 @@
+mod utils;
+use std::collections::HashMap;
+use utils::open_redis_connection;
+
+const PREFIX: &str = "{test-deliver}:";
+
+// SCHEDULE.CONFIG's reply
+type Config<DeadLetter, Deliver> = (
+    String,
+    DeadLetter,
+    String,
+    Deliver,
+    String,
+    u64,
+    String,
+    Option<Vec<String>>,
+);
+
+fn k(val: &str) -> String {
+    PREFIX.to_string() + val
+}
+
+fn cleanup() {
+    let mut con = open_redis_connection();
+    redis::cmd("DEL")
+        .arg(k("schedule"))
+        .arg(k("stream"))
+        .execute(&mut con);
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_deliver_config() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+
+    let result: redis::RedisResult<()> = redis::cmd("SCHEDULE.CONFIG")
+        .arg(k("schedule"))
+        .arg("DELIVER")
+        .arg("STREAM")
+        .arg(k("stream"))
+        .arg("MAXLEN")
+        .arg(100)
+        .query(&mut con);
+    assert!(result.is_err());
+
+    let _: () = redis::cmd("SCHEDULE.CONFIG")
+        .arg(k("schedule"))
+        .arg("DELIVER")
+        .arg("STREAM")
+        .arg(k("stream"))
+        .arg("MAXLEN")
+        .arg("~")
+        .arg(100)
+        .query(&mut con)?;
+    let (_, _, _, deliver, _, _, _, _): Config<Option<Vec<String>>, Vec<String>> =
+        redis::cmd("SCHEDULE.CONFIG")
+            .arg(k("schedule"))
+            .query(&mut con)?;
+    assert_eq!(
+        deliver,
+        vec![
+            "stream".to_string(),
+            k("stream"),
+            "maxlen".to_string(),
+            "100".to_string()
+        ]
+    );
+
+    let _: () = redis::cmd("SCHEDULE.CONFIG")
+        .arg(k("schedule"))
+        .arg("DELIVER")
+        .arg("NONE")
+        .query(&mut con)?;
+    let (_, _, _, deliver, _, _, _, _): Config<Option<Vec<String>>, Option<Vec<String>>> =
+        redis::cmd("SCHEDULE.CONFIG")
+            .arg(k("schedule"))
+            .query(&mut con)?;
+    assert_eq!(deliver, None);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+// The schedule's timer appends the due payload tasks to the stream
+fn test_deliver_to_stream() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+
+    let _: () = redis::cmd("SCHEDULE.CONFIG")
+        .arg(k("schedule"))
+        .arg("DELIVER")
+        .arg("STREAM")
+        .arg(k("stream"))
+        .query(&mut con)?;
+    let task_id: String = redis::cmd("SCHEDULE.ADD")
+        .arg(k("schedule"))
+        .arg("PX")
+        .arg(100)
+        .arg("PAYLOAD")
+        .arg("payload-1")
+        .query(&mut con)?;
+
+    std::thread::sleep(std::time::Duration::from_millis(300));
+    let entries: Vec<(String, HashMap<String, String>)> = redis::cmd("XRANGE")
+        .arg(k("stream"))
+        .arg("-")
+        .arg("+")
+        .query(&mut con)?;
+    assert_eq!(entries.len(), 1);
+    let fields = &entries[0].1;
+    assert_eq!(fields["id"], task_id);
+    assert_eq!(fields["payload"], "payload-1");
+    let scheduled: u64 = fields["scheduled"].parse().unwrap();
+    let delivered: u64 = fields["delivered"].parse().unwrap();
+    assert!(delivered >= scheduled);
+
+    let schedule: Vec<(u64, String, Vec<String>)> = redis::cmd("SCHEDULE.SCAN")
+        .arg(k("schedule"))
+        .query(&mut con)?;
+    assert!(schedule.is_empty());
+    Ok(())
+}
@@ -0,0 +1,168 @@
+mod utils;
+use utils::open_redis_connection;
+
+const PREFIX: &str = "{test-every}:";
+
+fn k(val: &str) -> String {
+    PREFIX.to_string() + val
+}
+
+fn cleanup() {
+    let mut con = open_redis_connection();
+    redis::cmd("DEL")
+        .arg(k("schedule"))
+        .arg(k("list"))
+        .arg(k("string"))
+        .execute(&mut con);
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_every_invalid_options() -> redis::RedisResult<()> {
+    let mut con = open_redis_connection();
+    let result: redis::RedisResult<String> = redis::cmd("SCHEDULE.ADD")
+        .arg(k("schedule"))
+        .arg(10)
+        .arg("EVERY")
+        .arg(0)
+        .arg("rpush")
+        .arg(k("list"))
+        .arg("item-1")
+        .query(&mut con);
+    assert!(result.is_err());
+
+    let result: redis::RedisResult<String> = redis::cmd("SCHEDULE.ADD")
+        .arg(k("schedule"))
+        .arg(10)
+        .arg("TIMES")
+        .arg(2)
+        .arg("rpush")
+        .arg(k("list"))
+        .arg("item-1")
+        .query(&mut con);
+    assert!(result.is_err());
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+// Executes a repeating task by hand until it runs out of runs
+fn test_every_task_runs_n_times() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+
+    let task_id: String = redis::cmd("SCHEDULE.ADD")
+        .arg(k("schedule"))
+        .arg(100)
+        .arg("EVERY")
+        .arg(100)
+        .arg("TIMES")
+        .arg(2)
+        .arg("rpush")
+        .arg(k("list"))
+        .arg("item-1")
+        .query(&mut con)?;
+
+    let _: () = redis::cmd("SCHEDULE.EXEC")
+        .arg(k("schedule"))
+        .arg(&task_id)
+        .query(&mut con)?;
+
+    let schedule: Vec<(u64, String, Vec<String>)> = redis::cmd("SCHEDULE.SCAN")
+        .arg(k("schedule"))
+        .query(&mut con)?;
+    assert_eq!(schedule.len(), 1);
+    assert_eq!(schedule[0].1, task_id);
+
+    let _: () = redis::cmd("SCHEDULE.EXEC")
+        .arg(k("schedule"))
+        .arg(&task_id)
+        .query(&mut con)?;
+
+    let schedule: Vec<(u64, String, Vec<String>)> = redis::cmd("SCHEDULE.SCAN")
+        .arg(k("schedule"))
+        .query(&mut con)?;
+    assert!(schedule.is_empty());
+
+    let list: Vec<String> = redis::cmd("LRANGE")
+        .arg(k("list"))
+        .arg(0)
+        .arg(-1)
+        .query(&mut con)?;
+    assert_eq!(list, vec!["item-1".to_string(), "item-1".to_string()]);
+
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_every_retry_is_rejected() -> redis::RedisResult<()> {
+    let mut con = open_redis_connection();
+    let result: redis::RedisResult<String> = redis::cmd("SCHEDULE.ADD")
+        .arg(k("schedule"))
+        .arg(100)
+        .arg("EVERY")
+        .arg(100)
+        .arg("TIMES")
+        .arg(2)
+        .arg("RETRY")
+        .arg(3)
+        .arg("BACKOFF")
+        .arg(1)
+        .arg("rpush")
+        .arg(k("list"))
+        .arg("item-1")
+        .query(&mut con);
+    assert!(result.is_err());
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+// A failing repeating task still runs n times, each run following the previous one
+fn test_every_failing_task_runs_n_times() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+    let _: () = redis::cmd("SET")
+        .arg(k("string"))
+        .arg("value")
+        .query(&mut con)?;
+
+    let task_id: String = redis::cmd("SCHEDULE.ADD")
+        .arg(k("schedule"))
+        .arg(100)
+        .arg("EVERY")
+        .arg(100)
+        .arg("TIMES")
+        .arg(2)
+        .arg("rpush")
+        .arg(k("string"))
+        .arg("item-1")
+        .query(&mut con)?;
+
+    let result: redis::RedisResult<()> = redis::cmd("SCHEDULE.EXEC")
+        .arg(k("schedule"))
+        .arg(&task_id)
+        .query(&mut con);
+    assert!(result.is_err());
+
+    // The next run is a whole interval away
+    let schedule: Vec<(u64, String, Vec<String>)> = redis::cmd("SCHEDULE.SCAN")
+        .arg(k("schedule"))
+        .query(&mut con)?;
+    assert_eq!(schedule.len(), 1);
+    let now: (u64, u64) = redis::cmd("TIME").query(&mut con)?;
+    assert!(schedule[0].0 >= now.0 * 1000 + 99_000);
+
+    let result: redis::RedisResult<()> = redis::cmd("SCHEDULE.EXEC")
+        .arg(k("schedule"))
+        .arg(&task_id)
+        .query(&mut con);
+    assert!(result.is_err());
+
+    let schedule: Vec<(u64, String, Vec<String>)> = redis::cmd("SCHEDULE.SCAN")
+        .arg(k("schedule"))
+        .query(&mut con)?;
+    assert!(schedule.is_empty());
+    Ok(())
+}
@@ -0,0 +1,84 @@
+mod utils;
+use utils::open_redis_connection;
+
+const PREFIX: &str = "{test-exec-due}:";
+
+// SCHEDULE.EXECDUE's reply
+type Summary = (
+    String,
+    u64,
+    String,
+    u64,
+    String,
+    Vec<Vec<String>>,
+    String,
+    u64,
+    String,
+    u64,
+);
+
+fn k(val: &str) -> String {
+    PREFIX.to_string() + val
+}
+
+fn cleanup() {
+    let mut con = open_redis_connection();
+    redis::cmd("DEL")
+        .arg(k("schedule"))
+        .arg(k("list"))
+        .arg(k("string"))
+        .execute(&mut con);
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+// A failing task must not stop the other due tasks from executing
+fn test_exec_due_isolates_failures() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+
+    // RPUSH fails on a string
+    redis::cmd("SET")
+        .arg(k("string"))
+        .arg("value")
+        .execute(&mut con);
+    let failing_task_id: String = redis::cmd("SCHEDULE.ADD")
+        .arg(k("schedule"))
+        .arg(100)
+        .arg("rpush")
+        .arg(k("string"))
+        .arg("item-1")
+        .query(&mut con)?;
+    let _: String = redis::cmd("SCHEDULE.ADD")
+        .arg(k("schedule"))
+        .arg(101)
+        .arg("rpush")
+        .arg(k("list"))
+        .arg("item-2")
+        .query(&mut con)?;
+
+    let (_, executed, _, failed, _, errors, _, _, _, backlog): Summary =
+        redis::cmd("SCHEDULE.EXECDUE")
+            .arg(k("schedule"))
+            .arg("PXAT")
+            .arg(u64::MAX)
+            .query(&mut con)?;
+    assert_eq!(executed, 1);
+    assert_eq!(failed, 1);
+    assert_eq!(backlog, 0);
+    assert_eq!(errors[0][0], failing_task_id);
+    assert!(errors[0][1].contains("WRONGTYPE"));
+
+    let list: Vec<String> = redis::cmd("LRANGE")
+        .arg(k("list"))
+        .arg(0)
+        .arg(-1)
+        .query(&mut con)?;
+    assert_eq!(list, vec!["item-2".to_string()]);
+
+    let schedule: Vec<Vec<String>> = redis::cmd("SCHEDULE.SCAN")
+        .arg(k("schedule"))
+        .query(&mut con)?;
+    assert!(schedule.is_empty());
+    Ok(())
+}
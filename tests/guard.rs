@@ -0,0 +1,111 @@
+mod utils;
+use utils::open_redis_connection;
+
+const PREFIX: &str = "{test-guard}:";
+
+fn k(val: &str) -> String {
+    PREFIX.to_string() + val
+}
+
+fn cleanup() {
+    let mut con = open_redis_connection();
+    redis::cmd("DEL")
+        .arg(k("schedule"))
+        .arg(k("status"))
+        .arg(k("list"))
+        .execute(&mut con);
+}
+
+fn add_guarded_task(
+    con: &mut dyn redis::ConnectionLike,
+    guard: &[&str],
+) -> redis::RedisResult<String> {
+    redis::cmd("SCHEDULE.ADD")
+        .arg(k("schedule"))
+        .arg(100)
+        .arg("IF")
+        .arg(guard.to_vec())
+        .arg("rpush")
+        .arg(k("list"))
+        .arg("item-1")
+        .query(con)
+}
+
+fn list_len(con: &mut dyn redis::ConnectionLike) -> redis::RedisResult<u64> {
+    redis::cmd("LLEN").arg(k("list")).query(con)
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_guard_eq() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+    let _: () = redis::cmd("SET")
+        .arg(k("status"))
+        .arg("paid")
+        .query(&mut con)?;
+
+    let status_key = k("status");
+    let task_id = add_guarded_task(&mut con, &["EQ", &status_key, "unpaid"])?;
+    let result: String = redis::cmd("SCHEDULE.EXEC")
+        .arg(k("schedule"))
+        .arg(&task_id)
+        .query(&mut con)?;
+    assert_eq!(result, "SKIPPED");
+    assert_eq!(list_len(&mut con)?, 0);
+
+    // The skipped task was dropped
+    let task: Option<Vec<redis::Value>> = redis::cmd("SCHEDULE.GET")
+        .arg(k("schedule"))
+        .arg(&task_id)
+        .query(&mut con)?;
+    assert_eq!(task, None);
+
+    let _: () = redis::cmd("SET")
+        .arg(k("status"))
+        .arg("unpaid")
+        .query(&mut con)?;
+    let task_id = add_guarded_task(&mut con, &["EQ", &status_key, "unpaid"])?;
+    let _: () = redis::cmd("SCHEDULE.EXEC")
+        .arg(k("schedule"))
+        .arg(&task_id)
+        .query(&mut con)?;
+    assert_eq!(list_len(&mut con)?, 1);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_guard_exists() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+    let status_key = k("status");
+
+    let exists_task_id = add_guarded_task(&mut con, &["EXISTS", &status_key])?;
+    let not_exists_task_id = add_guarded_task(&mut con, &["NOT", "EXISTS", &status_key])?;
+    for task_id in [exists_task_id, not_exists_task_id].iter() {
+        let _: () = redis::cmd("SCHEDULE.EXEC")
+            .arg(k("schedule"))
+            .arg(task_id)
+            .query(&mut con)?;
+    }
+    assert_eq!(list_len(&mut con)?, 1);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_guard_with_payload() -> redis::RedisResult<()> {
+    let mut con = open_redis_connection();
+    let result: redis::RedisResult<String> = redis::cmd("SCHEDULE.ADD")
+        .arg(k("schedule"))
+        .arg(100)
+        .arg("IF")
+        .arg("EXISTS")
+        .arg(k("status"))
+        .arg("PAYLOAD")
+        .arg("payload-1")
+        .query(&mut con);
+    assert!(result.is_err());
+    Ok(())
+}
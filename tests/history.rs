@@ -0,0 +1,106 @@
+mod utils;
+use utils::open_redis_connection;
+
+const PREFIX: &str = "{test-history}:";
+
+fn k(val: &str) -> String {
+    PREFIX.to_string() + val
+}
+
+fn cleanup() {
+    let mut con = open_redis_connection();
+    redis::cmd("DEL")
+        .arg(k("schedule"))
+        .arg(k("list"))
+        .arg(k("string"))
+        .execute(&mut con);
+}
+
+fn add_and_exec(con: &mut dyn redis::ConnectionLike, target: &str) -> redis::RedisResult<String> {
+    let task_id: String = redis::cmd("SCHEDULE.ADD")
+        .arg(k("schedule"))
+        .arg(100)
+        .arg("rpush")
+        .arg(k(target))
+        .arg("item")
+        .query(con)?;
+    let _: redis::RedisResult<()> = redis::cmd("SCHEDULE.EXEC")
+        .arg(k("schedule"))
+        .arg(&task_id)
+        .query(con);
+    Ok(task_id)
+}
+
+type HistoryEntry = (String, u64, u64, String, String);
+
+fn history(con: &mut dyn redis::ConnectionLike) -> redis::RedisResult<Vec<HistoryEntry>> {
+    redis::cmd("SCHEDULE.HISTORY")
+        .arg(k("schedule"))
+        .arg("COUNT")
+        .arg(10)
+        .query(con)
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_history_disabled() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+
+    let task_id = add_and_exec(&mut con, "list")?;
+    assert!(history(&mut con)?.is_empty());
+    let result: Option<Vec<String>> = redis::cmd("SCHEDULE.RESULT")
+        .arg(k("schedule"))
+        .arg(&task_id)
+        .query(&mut con)?;
+    assert_eq!(result, None);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_history() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+    let _: () = redis::cmd("SCHEDULE.CONFIG")
+        .arg(k("schedule"))
+        .arg("HISTORY")
+        .arg(2)
+        .query(&mut con)?;
+    let _: () = redis::cmd("SET")
+        .arg(k("string"))
+        .arg("value")
+        .query(&mut con)?;
+
+    let first_id = add_and_exec(&mut con, "list")?;
+    let succeeded_id = add_and_exec(&mut con, "list")?;
+    let failed_id = add_and_exec(&mut con, "string")?;
+
+    // Only the last 2 runs are kept, newest first
+    let entries = history(&mut con)?;
+    assert_eq!(entries.len(), 2);
+    let (task_id, scheduled, executed, outcome, output) = entries[0].clone();
+    assert_eq!(task_id, failed_id);
+    assert!(executed < scheduled);
+    assert_eq!(outcome, "failure");
+    assert!(output.contains("WRONGTYPE"));
+    assert_eq!(entries[1].0, succeeded_id);
+    assert_eq!(entries[1].3, "success");
+    assert_eq!(entries[1].4, "2");
+
+    let result: Vec<String> = redis::cmd("SCHEDULE.RESULT")
+        .arg(k("schedule"))
+        .arg(&succeeded_id)
+        .query(&mut con)?;
+    assert_eq!(
+        result[4..].to_vec(),
+        vec!["outcome", "success", "output", "2"]
+    );
+
+    let result: Option<Vec<String>> = redis::cmd("SCHEDULE.RESULT")
+        .arg(k("schedule"))
+        .arg(&first_id)
+        .query(&mut con)?;
+    assert_eq!(result, None);
+    Ok(())
+}
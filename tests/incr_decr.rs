@@ -87,3 +87,38 @@ fn test_decr_command() -> redis::RedisResult<()> {
     assert_eq!(list_size, 2);
     Ok(())
 }
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+#[cfg_attr(feature = "test_cluster", ignore)]
+fn test_incr_decr_out_of_range() -> redis::RedisResult<()> {
+    let mut con = open_redis_connection();
+
+    cleanup(&mut con, "test-incr-range");
+    let (task1, _) = create_table(&mut con, "test-incr-range", 600)?;
+
+    let result: redis::RedisResult<String> = redis::cmd("SCHEDULE.INCRBY")
+        .arg("test-incr-range-schedule")
+        .arg(&task1)
+        .arg("PX")
+        .arg(u64::MAX)
+        .query(&mut con);
+    assert!(result
+        .unwrap_err()
+        .to_string()
+        .contains("invalid expire time"));
+
+    let result: redis::RedisResult<String> = redis::cmd("SCHEDULE.DECRBY")
+        .arg("test-incr-range-schedule")
+        .arg(&task1)
+        .arg("PX")
+        .arg(u64::MAX)
+        .query(&mut con);
+    assert!(result
+        .unwrap_err()
+        .to_string()
+        .contains("invalid expire time"));
+
+    cleanup(&mut con, "test-incr-range");
+    Ok(())
+}
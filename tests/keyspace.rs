@@ -0,0 +1,129 @@
+mod utils;
+use std::thread;
+use std::time::{Duration, Instant};
+use utils::open_redis_connection;
+
+const PREFIX: &str = "{test-keyspace}:";
+
+fn k(val: &str) -> String {
+    PREFIX.to_string() + val
+}
+
+fn cleanup() {
+    let mut con = open_redis_connection();
+    redis::cmd("DEL")
+        .arg(k("schedule"))
+        .arg(k("renamed"))
+        .arg(k("list"))
+        .execute(&mut con);
+}
+
+fn add(con: &mut dyn redis::ConnectionLike, delay: u64) -> redis::RedisResult<String> {
+    redis::cmd("SCHEDULE.ADD")
+        .arg(k("schedule"))
+        .arg("PX")
+        .arg(delay)
+        .arg("rpush")
+        .arg(k("list"))
+        .arg("item")
+        .query(con)
+}
+
+fn list_len(con: &mut dyn redis::ConnectionLike) -> redis::RedisResult<u64> {
+    redis::cmd("LLEN").arg(k("list")).query(con)
+}
+
+// Polls the list until it has `len` items, or until the timeout
+fn wait_for_list_len(
+    con: &mut dyn redis::ConnectionLike,
+    len: u64,
+    timeout: Duration,
+) -> redis::RedisResult<bool> {
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        if list_len(con)? == len {
+            return Ok(true);
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    Ok(list_len(con)? == len)
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_keyspace_rename() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+
+    add(&mut con, 100)?;
+    redis::cmd("RENAME")
+        .arg(k("schedule"))
+        .arg(k("renamed"))
+        .query(&mut con)?;
+
+    // The renamed schedule still executes its task
+    assert!(wait_for_list_len(&mut con, 1, Duration::from_secs(2))?);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_keyspace_del() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+
+    add(&mut con, 100)?;
+    redis::cmd("DEL").arg(k("schedule")).query(&mut con)?;
+    thread::sleep(Duration::from_millis(300));
+
+    assert_eq!(list_len(&mut con)?, 0);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_keyspace_expire() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+
+    add(&mut con, 300)?;
+    redis::cmd("PEXPIRE")
+        .arg(k("schedule"))
+        .arg(100)
+        .query(&mut con)?;
+
+    // Adding tasks keeps the schedule's TTL
+    add(&mut con, 300)?;
+    let ttl: i64 = redis::cmd("PTTL").arg(k("schedule")).query(&mut con)?;
+    assert!(ttl > 0 && ttl <= 100);
+
+    thread::sleep(Duration::from_millis(500));
+    let exists: bool = redis::cmd("EXISTS").arg(k("schedule")).query(&mut con)?;
+    assert!(!exists);
+    assert_eq!(list_len(&mut con)?, 0);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(
+    any(not(feature = "integration_test"), feature = "test_cluster"),
+    ignore
+)]
+fn test_keyspace_flushdb() -> redis::RedisResult<()> {
+    let mut con = open_redis_connection();
+    // A database of its own, not to flush the other tests' keys
+    redis::cmd("SELECT").arg(8).query(&mut con)?;
+
+    add(&mut con, 100)?;
+    redis::cmd("FLUSHDB").query(&mut con)?;
+
+    // A new schedule under the same key runs on its own timer only, not on the
+    // flushed schedule's
+    let added = Instant::now();
+    add(&mut con, 300)?;
+    assert!(wait_for_list_len(&mut con, 1, Duration::from_secs(2))?);
+    assert!(added.elapsed() >= Duration::from_millis(300));
+
+    redis::cmd("FLUSHDB").query(&mut con)?;
+    Ok(())
+}
@@ -0,0 +1,127 @@
+mod utils;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use utils::open_redis_connection;
+
+const PREFIX: &str = "{test-misfire}:";
+
+fn k(val: &str) -> String {
+    PREFIX.to_string() + val
+}
+
+fn cleanup() {
+    let mut con = open_redis_connection();
+    redis::cmd("DEL")
+        .arg(k("schedule"))
+        .arg(k("list"))
+        .arg(k("dead"))
+        .execute(&mut con);
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+// Adds a task that was due `late_by` milliseconds ago
+fn add_overdue_task(
+    con: &mut dyn redis::ConnectionLike,
+    late_by: u64,
+    options: &[&str],
+) -> redis::RedisResult<String> {
+    redis::cmd("SCHEDULE.ADDAT")
+        .arg(k("schedule"))
+        .arg("PXAT")
+        .arg(now_millis() - late_by)
+        .arg(options.to_vec())
+        .arg("rpush")
+        .arg(k("list"))
+        .arg("item")
+        .query(con)
+}
+
+fn list_len(con: &mut dyn redis::ConnectionLike) -> redis::RedisResult<u64> {
+    redis::cmd("LLEN").arg(k("list")).query(con)
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_misfire_skip_if_late_by() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+    let _: () = redis::cmd("SCHEDULE.CONFIG")
+        .arg(k("schedule"))
+        .arg("MISFIRE")
+        .arg("SKIP_IF_LATE_BY")
+        .arg(60)
+        .query(&mut con)?;
+
+    add_overdue_task(&mut con, 120_000, &[])?;
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(list_len(&mut con)?, 0);
+
+    // A run late by less than the policy's limit executes
+    add_overdue_task(&mut con, 1_000, &[])?;
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(list_len(&mut con)?, 1);
+
+    let schedule: Vec<(u64, String, Vec<String>)> = redis::cmd("SCHEDULE.SCAN")
+        .arg(k("schedule"))
+        .query(&mut con)?;
+    assert!(schedule.is_empty());
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_misfire_dead_letter_if_late() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+    let _: () = redis::cmd("SCHEDULE.CONFIG")
+        .arg(k("schedule"))
+        .arg("DEADLETTER")
+        .arg("STREAM")
+        .arg(k("dead"))
+        .query(&mut con)?;
+
+    // The task's policy applies
+    add_overdue_task(&mut con, 120_000, &["MISFIRE", "DEADLETTER_IF_LATE", "60"])?;
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(list_len(&mut con)?, 0);
+
+    let dead_letters: u64 = redis::cmd("XLEN").arg(k("dead")).query(&mut con)?;
+    assert_eq!(dead_letters, 1);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_misfire_run_latest_only() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+
+    // An hourly task that missed its last 3 runs
+    let _: () = redis::cmd("SCHEDULE.REPLICATE")
+        .arg(k("schedule"))
+        .arg("PXAT")
+        .arg(now_millis() - 3 * 3_600_000)
+        .arg("task-a")
+        .arg("CRON")
+        .arg("0 * * * *")
+        .arg("MISFIRE")
+        .arg("RUN_LATEST_ONLY")
+        .arg("rpush")
+        .arg(k("list"))
+        .arg("item")
+        .query(&mut con)?;
+    thread::sleep(Duration::from_millis(300));
+    assert_eq!(list_len(&mut con)?, 1);
+
+    let schedule: Vec<(u64, String, Vec<String>)> = redis::cmd("SCHEDULE.SCAN")
+        .arg(k("schedule"))
+        .query(&mut con)?;
+    assert!(schedule[0].0 > now_millis());
+    Ok(())
+}
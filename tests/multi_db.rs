@@ -0,0 +1,195 @@
+mod utils;
+use std::thread;
+use std::time::Duration;
+use utils::open_redis_connection;
+
+const PREFIX: &str = "{test-multi-db}:";
+
+fn k(val: &str) -> String {
+    PREFIX.to_string() + val
+}
+
+fn cleanup(dbs: &[u32]) {
+    let mut con = open_redis_connection();
+    for db in dbs {
+        select(&mut con, *db).unwrap();
+        redis::cmd("DEL")
+            .arg(k("schedule"))
+            .arg(k("list"))
+            .execute(&mut con);
+    }
+}
+
+fn select(con: &mut dyn redis::ConnectionLike, db: u32) -> redis::RedisResult<()> {
+    redis::cmd("SELECT").arg(db).query(con)
+}
+
+fn add(con: &mut dyn redis::ConnectionLike, delay: u64) -> redis::RedisResult<String> {
+    redis::cmd("SCHEDULE.ADD")
+        .arg(k("schedule"))
+        .arg("PX")
+        .arg(delay)
+        .arg("rpush")
+        .arg(k("list"))
+        .arg("item")
+        .query(con)
+}
+
+fn list_len(con: &mut dyn redis::ConnectionLike, db: u32) -> redis::RedisResult<u64> {
+    select(con, db)?;
+    redis::cmd("LLEN").arg(k("list")).query(con)
+}
+
+#[test]
+#[cfg_attr(
+    any(not(feature = "integration_test"), feature = "test_cluster"),
+    ignore
+)]
+// The task executes in the database of its schedule
+fn test_multi_db_task_runs_in_its_database() -> redis::RedisResult<()> {
+    cleanup(&[0, 3]);
+    let mut con = open_redis_connection();
+
+    select(&mut con, 3)?;
+    add(&mut con, 100)?;
+    thread::sleep(Duration::from_millis(300));
+
+    assert_eq!(list_len(&mut con, 3)?, 1);
+    assert_eq!(list_len(&mut con, 0)?, 0);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(
+    any(not(feature = "integration_test"), feature = "test_cluster"),
+    ignore
+)]
+fn test_multi_db_swapdb() -> redis::RedisResult<()> {
+    cleanup(&[4, 5]);
+    let mut con = open_redis_connection();
+
+    select(&mut con, 4)?;
+    add(&mut con, 100)?;
+    redis::cmd("SWAPDB").arg(4).arg(5).query(&mut con)?;
+    thread::sleep(Duration::from_millis(300));
+
+    // The schedule is now in database 5, and runs there
+    assert_eq!(list_len(&mut con, 5)?, 1);
+    assert_eq!(list_len(&mut con, 4)?, 0);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(
+    any(not(feature = "integration_test"), feature = "test_cluster"),
+    ignore
+)]
+fn test_multi_db_move() -> redis::RedisResult<()> {
+    cleanup(&[6, 7]);
+    let mut con = open_redis_connection();
+
+    select(&mut con, 6)?;
+    add(&mut con, 100)?;
+    let moved: bool = redis::cmd("MOVE")
+        .arg(k("schedule"))
+        .arg(7)
+        .query(&mut con)?;
+    assert!(moved);
+    thread::sleep(Duration::from_millis(300));
+
+    assert_eq!(list_len(&mut con, 7)?, 1);
+    assert_eq!(list_len(&mut con, 6)?, 0);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(
+    any(not(feature = "integration_test"), feature = "test_cluster"),
+    ignore
+)]
+// A client waiting for a task isn't released by the same task id running in another database
+fn test_multi_db_wait() -> redis::RedisResult<()> {
+    cleanup(&[9, 10]);
+    let mut con = open_redis_connection();
+
+    for db in &[9, 10] {
+        select(&mut con, *db)?;
+        let _: String = redis::cmd("SCHEDULE.ADD")
+            .arg(k("schedule"))
+            .arg(100)
+            .arg("ID")
+            .arg("job")
+            .arg("rpush")
+            .arg(k("list"))
+            .arg("item")
+            .query(&mut con)?;
+    }
+
+    let waiter = thread::spawn(|| {
+        let mut con = open_redis_connection();
+        select(&mut con, 9)?;
+        let run: redis::RedisResult<Option<(String, String)>> = redis::cmd("SCHEDULE.WAIT")
+            .arg(k("schedule"))
+            .arg("job")
+            .arg("PX")
+            .arg(500)
+            .query(&mut con);
+        run
+    });
+    thread::sleep(Duration::from_millis(100));
+
+    select(&mut con, 10)?;
+    let _: () = redis::cmd("SCHEDULE.EXEC")
+        .arg(k("schedule"))
+        .arg("job")
+        .query(&mut con)?;
+    assert_eq!(list_len(&mut con, 10)?, 1);
+
+    assert_eq!(waiter.join().unwrap()?, None);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(
+    any(not(feature = "integration_test"), feature = "test_cluster"),
+    ignore
+)]
+// A client blocked on a schedule pops the due payload tasks of its own database only
+fn test_multi_db_bpopdue() -> redis::RedisResult<()> {
+    cleanup(&[11, 12]);
+    let mut con = open_redis_connection();
+
+    let consumer = thread::spawn(|| {
+        let mut con = open_redis_connection();
+        select(&mut con, 11)?;
+        let popped: redis::RedisResult<Option<Vec<(String, u64, String)>>> =
+            redis::cmd("SCHEDULE.BPOPDUE")
+                .arg(k("schedule"))
+                .arg(5)
+                .query(&mut con);
+        popped
+    });
+    thread::sleep(Duration::from_millis(100));
+
+    for db in &[12, 11] {
+        select(&mut con, *db)?;
+        let _: String = redis::cmd("SCHEDULE.ADD")
+            .arg(k("schedule"))
+            .arg("PX")
+            .arg(100)
+            .arg("PAYLOAD")
+            .arg(format!("payload-{}", db))
+            .query(&mut con)?;
+    }
+
+    let popped = consumer.join().unwrap()?.unwrap();
+    assert_eq!(popped.len(), 1);
+    assert_eq!(popped[0].2, "payload-11");
+
+    select(&mut con, 12)?;
+    let schedule: Vec<(u64, String, Vec<String>)> = redis::cmd("SCHEDULE.SCAN")
+        .arg(k("schedule"))
+        .query(&mut con)?;
+    assert_eq!(schedule.len(), 1);
+    Ok(())
+}
@@ -0,0 +1,75 @@
+mod utils;
+use utils::open_redis_connection;
+
+const PREFIX: &str = "{test-popdue}:";
+
+fn k(val: &str) -> String {
+    PREFIX.to_string() + val
+}
+
+fn cleanup() {
+    let mut con = open_redis_connection();
+    redis::cmd("DEL").arg(k("schedule")).execute(&mut con);
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_payload_with_command() -> redis::RedisResult<()> {
+    let mut con = open_redis_connection();
+    let result: redis::RedisResult<String> = redis::cmd("SCHEDULE.ADD")
+        .arg(k("schedule"))
+        .arg(0)
+        .arg("PAYLOAD")
+        .arg("payload-1")
+        .arg("rpush")
+        .arg(k("list"))
+        .arg("item-1")
+        .query(&mut con);
+    assert!(result.is_err());
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_popdue() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+
+    let mut task_ids = Vec::new();
+    for (delay, payload) in [(0, "payload-1"), (0, "payload-2"), (100, "payload-3")].iter() {
+        let task_id: String = redis::cmd("SCHEDULE.ADD")
+            .arg(k("schedule"))
+            .arg(*delay)
+            .arg("PAYLOAD")
+            .arg(*payload)
+            .query(&mut con)?;
+        task_ids.push(task_id);
+    }
+
+    let popped: Vec<(String, u64, String)> = redis::cmd("SCHEDULE.POPDUE")
+        .arg(k("schedule"))
+        .query(&mut con)?;
+    assert_eq!(popped.len(), 1);
+    assert_eq!(popped[0].0, task_ids[0]);
+    assert_eq!(popped[0].2, "payload-1");
+
+    let popped: Vec<(String, u64, String)> = redis::cmd("SCHEDULE.POPDUE")
+        .arg(k("schedule"))
+        .arg("COUNT")
+        .arg(10)
+        .query(&mut con)?;
+    assert_eq!(popped.len(), 1);
+    assert_eq!(popped[0].2, "payload-2");
+
+    // The last one isn't due yet
+    let schedule: Vec<(u64, String, Vec<String>)> = redis::cmd("SCHEDULE.SCAN")
+        .arg(k("schedule"))
+        .query(&mut con)?;
+    assert_eq!(schedule.len(), 1);
+    assert_eq!(schedule[0].1, task_ids[2]);
+    assert_eq!(
+        schedule[0].2,
+        vec!["PAYLOAD".to_string(), "payload-3".to_string()]
+    );
+    Ok(())
+}
@@ -0,0 +1,91 @@
+mod utils;
+use utils::open_redis_connection;
+
+const PREFIX: &str = "{test-retry}:";
+
+fn k(val: &str) -> String {
+    PREFIX.to_string() + val
+}
+
+fn cleanup() {
+    let mut con = open_redis_connection();
+    redis::cmd("DEL")
+        .arg(k("schedule"))
+        .arg(k("string"))
+        .execute(&mut con);
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_retry_invalid_options() -> redis::RedisResult<()> {
+    let mut con = open_redis_connection();
+    let result: redis::RedisResult<String> = redis::cmd("SCHEDULE.ADD")
+        .arg(k("schedule"))
+        .arg(10)
+        .arg("RETRY")
+        .arg(3)
+        .arg("rpush")
+        .arg(k("string"))
+        .arg("item-1")
+        .query(&mut con);
+    assert!(result.is_err());
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+// A failing task goes back into the schedule until it runs out of retries
+fn test_failed_task_is_retried() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+
+    // RPUSH fails on a string
+    redis::cmd("SET")
+        .arg(k("string"))
+        .arg("value")
+        .execute(&mut con);
+    let task_id: String = redis::cmd("SCHEDULE.ADD")
+        .arg(k("schedule"))
+        .arg(100)
+        .arg("RETRY")
+        .arg(2)
+        .arg("BACKOFF")
+        .arg(100)
+        .arg("rpush")
+        .arg(k("string"))
+        .arg("item-1")
+        .query(&mut con)?;
+
+    for attempt in 1..=2 {
+        let result: redis::RedisResult<()> = redis::cmd("SCHEDULE.EXEC")
+            .arg(k("schedule"))
+            .arg(&task_id)
+            .query(&mut con);
+        assert!(result.is_err());
+
+        let task: Vec<redis::Value> = redis::cmd("SCHEDULE.GET")
+            .arg(k("schedule"))
+            .arg(&task_id)
+            .query(&mut con)?;
+        assert_eq!(task[7], redis::Value::Int(attempt));
+        match &task[9] {
+            redis::Value::Data(error) => {
+                assert!(String::from_utf8_lossy(error).contains("WRONGTYPE"))
+            }
+            value => panic!("unexpected last_error {:?}", value),
+        }
+    }
+
+    let result: redis::RedisResult<()> = redis::cmd("SCHEDULE.EXEC")
+        .arg(k("schedule"))
+        .arg(&task_id)
+        .query(&mut con);
+    assert!(result.is_err());
+
+    let task: Option<Vec<redis::Value>> = redis::cmd("SCHEDULE.GET")
+        .arg(k("schedule"))
+        .arg(&task_id)
+        .query(&mut con)?;
+    assert_eq!(task, None);
+    Ok(())
+}
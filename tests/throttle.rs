@@ -0,0 +1,81 @@
+mod utils;
+use utils::open_redis_connection;
+
+const PREFIX: &str = "{test-throttle}:";
+
+fn k(val: &str) -> String {
+    PREFIX.to_string() + val
+}
+
+fn cleanup() {
+    let mut con = open_redis_connection();
+    redis::cmd("DEL")
+        .arg(k("schedule"))
+        .arg(k("list"))
+        .execute(&mut con);
+}
+
+fn throttle(
+    con: &mut dyn redis::ConnectionLike,
+    window: u64,
+    item: &str,
+) -> redis::RedisResult<u64> {
+    let reply: redis::Value = redis::cmd("SCHEDULE.THROTTLE")
+        .arg(k("schedule"))
+        .arg("notify")
+        .arg("PX")
+        .arg(window)
+        .arg("rpush")
+        .arg(k("list"))
+        .arg(item)
+        .query(con)?;
+    // The timestamp is a bulk string, as SCHEDULE.SETAT replies it
+    assert!(matches!(reply, redis::Value::Data(_)));
+    redis::from_redis_value(&reply)
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_throttle_keeps_the_pending_task() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+
+    let first = throttle(&mut con, 100_000, "item-1")?;
+    let second = throttle(&mut con, 200_000, "item-2")?;
+    assert_eq!(first, second);
+
+    let schedule: Vec<(u64, String, Vec<String>)> = redis::cmd("SCHEDULE.SCAN")
+        .arg(k("schedule"))
+        .query(&mut con)?;
+    assert_eq!(
+        schedule,
+        vec![(
+            first,
+            "notify".to_string(),
+            vec!["rpush".to_string(), k("list"), "item-1".to_string()]
+        )]
+    );
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_throttle_once_per_window() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+
+    for item in &["item-1", "item-2", "item-3"] {
+        throttle(&mut con, 500, item)?;
+    }
+    std::thread::sleep(std::time::Duration::from_millis(1000));
+    throttle(&mut con, 500, "item-4")?;
+    std::thread::sleep(std::time::Duration::from_millis(1000));
+
+    let list: Vec<String> = redis::cmd("LRANGE")
+        .arg(k("list"))
+        .arg(0)
+        .arg(-1)
+        .query(&mut con)?;
+    assert_eq!(list, vec!["item-1".to_string(), "item-4".to_string()]);
+    Ok(())
+}
@@ -39,3 +39,41 @@ fn test_validate_commands_with_keyless_command() -> redis::RedisResult<()> {
     assert!(result.is_err());
     Ok(())
 }
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_validate_timestamp_too_far() -> redis::RedisResult<()> {
+    let mut con = open_redis_connection();
+    let result: redis::RedisResult<String> = redis::cmd("SCHEDULE.ADDAT")
+        .arg("test-schedule:{1}")
+        .arg("PXAT")
+        .arg("9300000000000000000")
+        .arg("INCR")
+        .arg("test-counter:{1}")
+        .query(&mut con);
+
+    assert!(result
+        .unwrap_err()
+        .to_string()
+        .contains("invalid expire time"));
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_validate_delay_too_long() -> redis::RedisResult<()> {
+    let mut con = open_redis_connection();
+    let result: redis::RedisResult<String> = redis::cmd("SCHEDULE.ADD")
+        .arg("test-schedule:{1}")
+        .arg("PX")
+        .arg(u64::MAX)
+        .arg("INCR")
+        .arg("test-counter:{1}")
+        .query(&mut con);
+
+    assert!(result
+        .unwrap_err()
+        .to_string()
+        .contains("invalid expire time"));
+    Ok(())
+}
@@ -0,0 +1,186 @@
+mod utils;
+use std::thread;
+use std::time::Duration;
+use utils::open_redis_connection;
+
+const PREFIX: &str = "{test-wait}:";
+
+fn k(val: &str) -> String {
+    PREFIX.to_string() + val
+}
+
+fn cleanup() {
+    let mut con = open_redis_connection();
+    redis::cmd("DEL")
+        .arg(k("schedule"))
+        .arg(k("list"))
+        .execute(&mut con);
+}
+
+fn add_task(con: &mut dyn redis::ConnectionLike) -> redis::RedisResult<String> {
+    redis::cmd("SCHEDULE.ADD")
+        .arg(k("schedule"))
+        .arg(100)
+        .arg("rpush")
+        .arg(k("list"))
+        .arg("item")
+        .query(con)
+}
+
+// Waits for the task on another connection
+fn wait_in_background(task_id: &str) -> thread::JoinHandle<Option<(String, String)>> {
+    let task_id = task_id.to_string();
+    thread::spawn(move || {
+        let mut con = open_redis_connection();
+        redis::cmd("SCHEDULE.WAIT")
+            .arg(k("schedule"))
+            .arg(task_id)
+            .arg(5)
+            .query(&mut con)
+            .unwrap()
+    })
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_wait_until_run() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+    let task_id = add_task(&mut con)?;
+
+    let waiter = wait_in_background(&task_id);
+    thread::sleep(Duration::from_millis(200));
+    let _: () = redis::cmd("SCHEDULE.EXEC")
+        .arg(k("schedule"))
+        .arg(&task_id)
+        .query(&mut con)?;
+
+    assert_eq!(
+        waiter.join().unwrap(),
+        Some(("success".to_string(), "1".to_string()))
+    );
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_wait_removed_task() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+    let task_id = add_task(&mut con)?;
+
+    let waiter = wait_in_background(&task_id);
+    thread::sleep(Duration::from_millis(200));
+    let _: () = redis::cmd("SCHEDULE.REM")
+        .arg(k("schedule"))
+        .arg(&task_id)
+        .query(&mut con)?;
+
+    assert_eq!(waiter.join().unwrap(), None);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_wait_timeout() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+    let task_id = add_task(&mut con)?;
+
+    let result: Option<(String, String)> = redis::cmd("SCHEDULE.WAIT")
+        .arg(k("schedule"))
+        .arg(&task_id)
+        .arg("PX")
+        .arg(100)
+        .query(&mut con)?;
+    assert_eq!(result, None);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+fn test_wait_task_already_ran() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+    let _: () = redis::cmd("SCHEDULE.CONFIG")
+        .arg(k("schedule"))
+        .arg("HISTORY")
+        .arg(10)
+        .query(&mut con)?;
+    let task_id = add_task(&mut con)?;
+    let _: () = redis::cmd("SCHEDULE.EXEC")
+        .arg(k("schedule"))
+        .arg(&task_id)
+        .query(&mut con)?;
+
+    let result: Option<(String, String)> = redis::cmd("SCHEDULE.WAIT")
+        .arg(k("schedule"))
+        .arg(&task_id)
+        .arg(0)
+        .query(&mut con)?;
+    assert_eq!(result, Some(("success".to_string(), "1".to_string())));
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+// The first attempt fails, the client waits for the retry
+fn test_wait_for_retry() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+    let _: () = redis::cmd("SET")
+        .arg(k("list"))
+        .arg("not a list")
+        .query(&mut con)?;
+    let task_id: String = redis::cmd("SCHEDULE.ADD")
+        .arg(k("schedule"))
+        .arg(60)
+        .arg("RETRY")
+        .arg(1)
+        .arg("BACKOFF")
+        .arg("PX")
+        .arg(100)
+        .arg("rpush")
+        .arg(k("list"))
+        .arg("item")
+        .query(&mut con)?;
+
+    let waiter = wait_in_background(&task_id);
+    thread::sleep(Duration::from_millis(200));
+    let result: redis::RedisResult<()> = redis::cmd("SCHEDULE.EXEC")
+        .arg(k("schedule"))
+        .arg(&task_id)
+        .query(&mut con);
+    assert!(result.is_err());
+    let _: () = redis::cmd("DEL").arg(k("list")).query(&mut con)?;
+
+    assert_eq!(
+        waiter.join().unwrap(),
+        Some(("success".to_string(), "1".to_string()))
+    );
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(not(feature = "integration_test"), ignore)]
+// Deleting the schedule releases the client right away
+fn test_wait_schedule_deleted() -> redis::RedisResult<()> {
+    cleanup();
+    let mut con = open_redis_connection();
+    let task_id: String = redis::cmd("SCHEDULE.ADD")
+        .arg(k("schedule"))
+        .arg(60)
+        .arg("rpush")
+        .arg(k("list"))
+        .arg("item")
+        .query(&mut con)?;
+
+    let started = std::time::Instant::now();
+    let waiter = wait_in_background(&task_id);
+    thread::sleep(Duration::from_millis(50));
+    let _: () = redis::cmd("DEL").arg(k("schedule")).query(&mut con)?;
+
+    assert_eq!(waiter.join().unwrap(), None);
+    assert!(started.elapsed() < Duration::from_secs(5));
+    Ok(())
+}